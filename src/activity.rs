@@ -0,0 +1,35 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::TimerError;
+use crate::persistence::write_json;
+
+/// When `timer` last changed tasks, kept in `activity.json` in the data directory.
+#[derive(Serialize, Deserialize)]
+struct Activity {
+    last_used: SystemTime,
+}
+
+fn activity_path(data_dir: &Path) -> PathBuf {
+    data_dir.join("activity.json")
+}
+
+/// The last time `timer` changed tasks, `None` before the first recorded change. The caller
+/// holds the data directory lock.
+pub fn last_activity(data_dir: &Path) -> Result<Option<SystemTime>, TimerError> {
+    let path = activity_path(data_dir);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let contents = fs::read_to_string(&path).map_err(TimerError::io(&path))?;
+    let activity: Activity = serde_json::from_str(&contents).map_err(|err| TimerError::CorruptFile(path, err))?;
+    Ok(Some(activity.last_used))
+}
+
+/// Records a change made at `now`. The caller holds the data directory lock.
+pub fn record_activity(data_dir: &Path, now: SystemTime) -> Result<(), TimerError> {
+    write_json(data_dir, &activity_path(data_dir), &Activity { last_used: now })
+}
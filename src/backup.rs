@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Utc};
+
+use crate::clock::{Clock, SystemClock};
+use crate::error::TimerError;
+use crate::storage::json::read_tasks;
+use crate::task::Task;
+
+// Backups are named in UTC, with a `Z` at the end. Older versions used local time
+// without it.
+const TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%S%.6f";
+
+/// A copy of a task list file taken before it was overwritten, named `<list>.<timestamp>`.
+pub struct Backup {
+    pub name: String,
+    pub list: String,
+    pub created: DateTime<Utc>,
+    pub path: PathBuf,
+}
+
+impl Backup {
+    fn from_path(path: PathBuf) -> Option<Backup> {
+        let name = path.file_name()?.to_str()?.strip_suffix(".json")?.to_string();
+        // List names cannot contain dots, the first one ends the list name.
+        let (list, timestamp) = name.split_once('.')?;
+        let created = match timestamp.strip_suffix('Z') {
+            Some(utc) => NaiveDateTime::parse_from_str(utc, TIMESTAMP_FORMAT).ok()?.and_utc(),
+            None => {
+                let local = NaiveDateTime::parse_from_str(timestamp, TIMESTAMP_FORMAT).ok()?;
+                Local.from_local_datetime(&local).earliest()?.to_utc()
+            }
+        };
+        Some(Backup {
+            list: list.to_string(),
+            name,
+            created,
+            path,
+        })
+    }
+
+    pub fn load(&self) -> Result<HashMap<u32, Task>, TimerError> {
+        read_tasks(&self.path)
+    }
+}
+
+pub fn backup_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join("backups")
+}
+
+/// Copies the file of `list` into the backup directory, then deletes the oldest
+/// backups of that list so at most `keep` are left. Nothing is kept when `keep` is 0.
+pub fn create_backup(data_dir: &Path, list: &str, file: &Path, keep: usize) -> Result<(), TimerError> {
+    if keep == 0 || !file.exists() {
+        return Ok(());
+    }
+    let path = new_backup_path(data_dir, list)?;
+    fs::copy(file, &path).map_err(TimerError::io(&path))?;
+    prune_backups(data_dir, list, keep)
+}
+
+/// Like `create_backup`, for storage that does not keep lists in files. The tasks are
+/// written as a task file, so backups can be restored into any storage.
+#[cfg(feature = "sqlite")]
+pub fn backup_tasks(data_dir: &Path, list: &str, tasks: &HashMap<u32, Task>, keep: usize) -> Result<(), TimerError> {
+    if keep == 0 || tasks.is_empty() {
+        return Ok(());
+    }
+    let path = new_backup_path(data_dir, list)?;
+    let file = serde_json::json!({ "version": crate::schema::CURRENT_VERSION, "tasks": tasks });
+    crate::persistence::write_json(data_dir, &path, &file)?;
+    prune_backups(data_dir, list, keep)
+}
+
+/// Whether a save of `list` should be backed up: always with a zero `interval`, otherwise
+/// when the last backup of the list is at least `interval` old, or there is none.
+pub fn backup_due(data_dir: &Path, list: &str, interval: Duration) -> Result<bool, TimerError> {
+    if interval.is_zero() {
+        return Ok(true);
+    }
+    let now = DateTime::<Utc>::from(SystemClock.now());
+    let recent = |backup: &Backup| (now - backup.created).to_std().is_ok_and(|age| age < interval);
+    Ok(!list_backups(data_dir)?.iter().any(|backup| backup.list == list && recent(backup)))
+}
+
+fn new_backup_path(data_dir: &Path, list: &str) -> Result<PathBuf, TimerError> {
+    let dir = backup_dir(data_dir);
+    fs::create_dir_all(&dir).map_err(TimerError::io(&dir))?;
+    // Always the system clock: backup names must stay unique and in the order they were
+    // taken, which a fake clock of a `TaskStore` does not guarantee.
+    let timestamp = DateTime::<Utc>::from(SystemClock.now()).format(TIMESTAMP_FORMAT);
+    Ok(dir.join(format!("{list}.{timestamp}Z.json")))
+}
+
+fn prune_backups(data_dir: &Path, list: &str, keep: usize) -> Result<(), TimerError> {
+    let backups: Vec<Backup> = list_backups(data_dir)?.into_iter().filter(|backup| backup.list == list).collect();
+    let excess = backups.len().saturating_sub(keep);
+    for backup in &backups[..excess] {
+        fs::remove_file(&backup.path).map_err(TimerError::io(&backup.path))?;
+    }
+    Ok(())
+}
+
+/// Every backup in the data directory, oldest first.
+pub fn list_backups(data_dir: &Path) -> Result<Vec<Backup>, TimerError> {
+    let dir = backup_dir(data_dir);
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(TimerError::Io(dir, err)),
+    };
+    let mut backups = Vec::new();
+    for entry in entries {
+        let path = entry.map_err(TimerError::io(&dir))?.path();
+        backups.extend(Backup::from_path(path));
+    }
+    backups.sort_by(|a, b| a.created.cmp(&b.created).then_with(|| a.list.cmp(&b.list)));
+    Ok(backups)
+}
+
+pub fn find_backup(data_dir: &Path, name: &str) -> Result<Backup, TimerError> {
+    list_backups(data_dir)?
+        .into_iter()
+        .find(|backup| backup.name == name)
+        .ok_or_else(|| TimerError::BackupNotFound(name.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backups_are_rotated_per_list() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let data_dir = temp_dir.path();
+        let file = data_dir.join("current.json");
+        fs::write(&file, "{}").unwrap();
+        for _ in 0..4 {
+            create_backup(data_dir, "current", &file, 3).unwrap();
+        }
+        create_backup(data_dir, "archive", &file, 3).unwrap();
+        create_backup(data_dir, "archive", &file, 0).unwrap();
+
+        let backups = list_backups(data_dir).unwrap();
+        let current: Vec<&Backup> = backups.iter().filter(|backup| backup.list == "current").collect();
+        assert_eq!(3, current.len());
+        assert_eq!(4, backups.len());
+        let found = find_backup(data_dir, &current[0].name).unwrap();
+        assert!(found.load().unwrap().is_empty());
+        assert!(find_backup(data_dir, "current.nope").is_err());
+    }
+
+    #[test]
+    fn backups_are_due_after_the_interval() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let data_dir = temp_dir.path();
+        let file = data_dir.join("current.json");
+        fs::write(&file, "{}").unwrap();
+        let hour = Duration::from_secs(3600);
+        assert!(backup_due(data_dir, "current", hour).unwrap());
+        create_backup(data_dir, "current", &file, 3).unwrap();
+        assert!(!backup_due(data_dir, "current", hour).unwrap());
+        assert!(backup_due(data_dir, "current", Duration::ZERO).unwrap());
+        assert!(backup_due(data_dir, "archive", hour).unwrap());
+
+        let backup = list_backups(data_dir).unwrap().remove(0);
+        assert!(backup.name.ends_with('Z'));
+        let created = backup.created - hour;
+        let older = backup_dir(data_dir).join(format!("current.{}Z.json", created.format(TIMESTAMP_FORMAT)));
+        fs::rename(&backup.path, older).unwrap();
+        assert!(backup_due(data_dir, "current", hour).unwrap());
+    }
+
+    #[test]
+    fn local_backup_names_are_still_read() {
+        let path = PathBuf::from("current.20240304T101502.120031.json");
+        let backup = Backup::from_path(path).unwrap();
+        let local = Local.with_ymd_and_hms(2024, 3, 4, 10, 15, 2).unwrap();
+        assert_eq!(local.timestamp(), backup.created.timestamp());
+        let backup = Backup::from_path(PathBuf::from("current.20240304T101502.120031Z.json")).unwrap();
+        assert_eq!(Utc.with_ymd_and_hms(2024, 3, 4, 10, 15, 2).unwrap().timestamp(), backup.created.timestamp());
+    }
+}
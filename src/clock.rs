@@ -0,0 +1,187 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime};
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+/// Readings of the clocks that keep counting when the wall clock is changed. Comparing
+/// the marks taken at the start and the end of a session tells how long it really lasted
+/// and how long the machine was suspended in between.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ClockMark {
+    /// Changes when the machine restarts, the other readings are only comparable within a boot.
+    pub boot_id: String,
+    /// Time since boot, suspends included.
+    pub since_boot: Duration,
+    /// Time since boot the machine was awake.
+    pub awake: Duration,
+}
+
+impl ClockMark {
+    /// The mark as it was `duration` earlier, assuming the machine was awake meanwhile.
+    /// `None` when that was before the machine booted.
+    pub fn before(&self, duration: Duration) -> Option<ClockMark> {
+        Some(ClockMark {
+            boot_id: self.boot_id.clone(),
+            since_boot: self.since_boot.checked_sub(duration)?,
+            awake: self.awake.checked_sub(duration)?,
+        })
+    }
+}
+
+/// Source of the current time. Everything that needs "now" gets it from a clock, or as
+/// a `now` argument taken from one, so tests can run on a fake clock. Clocks are `Send`
+/// like storages, so a `TaskStore` can be moved to another thread.
+pub trait Clock: Send {
+    fn now(&self) -> SystemTime;
+
+    fn local_now(&self) -> DateTime<Local> {
+        self.now().into()
+    }
+
+    /// `None` where the system does not offer a boot clock.
+    fn mark(&self) -> Option<ClockMark> {
+        None
+    }
+}
+
+/// The system's wall clock.
+#[derive(Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    #[cfg(target_os = "linux")]
+    fn mark(&self) -> Option<ClockMark> {
+        let boot_id = std::fs::read_to_string("/proc/sys/kernel/random/boot_id").ok()?;
+        Some(ClockMark {
+            boot_id: boot_id.trim().to_string(),
+            since_boot: read_clock(libc::CLOCK_BOOTTIME)?,
+            awake: read_clock(libc::CLOCK_MONOTONIC)?,
+        })
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn read_clock(clock: libc::clockid_t) -> Option<Duration> {
+    let mut time = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    // SAFETY: `time` is a valid timespec for clock_gettime to write to.
+    if unsafe { libc::clock_gettime(clock, &mut time) } != 0 {
+        return None;
+    }
+    Some(Duration::new(time.tv_sec as u64, time.tv_nsec as u32))
+}
+
+/// A clock that only moves when told to. Clones share the same time, also across
+/// threads, so a test can keep one to advance the clock of a `TaskStore`.
+#[derive(Clone)]
+pub struct FakeClock {
+    state: Arc<Mutex<FakeTime>>,
+}
+
+struct FakeTime {
+    now: SystemTime,
+    since_boot: Duration,
+    awake: Duration,
+    boot: u32,
+}
+
+impl FakeClock {
+    pub fn new(now: impl Into<SystemTime>) -> FakeClock {
+        let time = FakeTime { now: now.into(), since_boot: Duration::ZERO, awake: Duration::ZERO, boot: 0 };
+        FakeClock { state: Arc::new(Mutex::new(time)) }
+    }
+
+    // A panic while the lock was held cannot leave the time half changed, so a poisoned
+    // lock is still used.
+    fn time(&self) -> MutexGuard<'_, FakeTime> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Changes the wall clock, like a user or NTP would. The boot clocks do not move.
+    pub fn set(&self, now: impl Into<SystemTime>) {
+        self.time().now = now.into();
+    }
+
+    /// Lets time pass with the machine awake.
+    pub fn advance(&self, duration: Duration) {
+        let mut time = self.time();
+        time.now += duration;
+        time.since_boot += duration;
+        time.awake += duration;
+    }
+
+    /// Lets time pass with the machine suspended.
+    pub fn suspend(&self, duration: Duration) {
+        let mut time = self.time();
+        time.now += duration;
+        time.since_boot += duration;
+    }
+
+    /// Lets time pass with the machine turned off, it boots again afterwards.
+    pub fn restart(&self, duration: Duration) {
+        let mut time = self.time();
+        time.now += duration;
+        time.since_boot = Duration::ZERO;
+        time.awake = Duration::ZERO;
+        time.boot += 1;
+    }
+}
+
+impl Clock for FakeClock {
+    fn now(&self) -> SystemTime {
+        self.time().now
+    }
+
+    fn mark(&self) -> Option<ClockMark> {
+        let time = self.time();
+        Some(ClockMark {
+            boot_id: format!("fake-{}", time.boot),
+            since_boot: time.since_boot,
+            awake: time.awake,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::TimeZone;
+
+    #[test]
+    fn fake_clock_clones_share_the_time() {
+        let start = Local.with_ymd_and_hms(2024, 3, 4, 23, 30, 0).unwrap();
+        let clock = FakeClock::new(start);
+        let shared = clock.clone();
+        shared.advance(Duration::new(3600, 0));
+        assert_eq!(Local.with_ymd_and_hms(2024, 3, 5, 0, 30, 0).unwrap(), clock.local_now());
+        clock.set(start);
+        assert_eq!(SystemTime::from(start), shared.now());
+
+        let mark = clock.mark().unwrap();
+        clock.suspend(Duration::new(60, 0));
+        let later = clock.mark().unwrap();
+        assert_eq!(mark.since_boot + Duration::new(60, 0), later.since_boot);
+        assert_eq!(mark.awake, later.awake);
+        clock.restart(Duration::new(60, 0));
+        assert_ne!(mark.boot_id, clock.mark().unwrap().boot_id);
+
+        std::thread::spawn(move || shared.advance(Duration::new(60, 0))).join().unwrap();
+        assert_eq!(SystemTime::from(start) + Duration::new(180, 0), clock.now());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn system_clock_marks_move_forward() {
+        let Some(first) = SystemClock.mark() else {
+            return;
+        };
+        let second = SystemClock.mark().unwrap();
+        assert_eq!(first.boot_id, second.boot_id);
+        assert!(second.since_boot >= first.since_boot && second.awake >= first.awake);
+    }
+}
@@ -0,0 +1,129 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::TimerError;
+use crate::storage::StorageKind;
+use crate::task::parse_time;
+
+const APP_NAME: &str = "simple-task-timer";
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub data_dir: Option<PathBuf>,
+    /// Makes `start` stop every other running task, like `switch`.
+    pub exclusive_start: bool,
+    /// Backups kept for each task list, 0 disables them.
+    pub backup_count: usize,
+    /// Saves within this long of the last backup of a list, such as `1h`, are not backed
+    /// up, unless they remove or overwrite tasks. `0s` backs up every save.
+    pub backup_interval: String,
+    /// Where the tasks are kept, `json` files or a `sqlite` database.
+    pub storage: StorageKind,
+    /// Sessions longer than this, such as `12h`, are questioned when they stop.
+    pub max_session: String,
+    /// Breaks shorter than this, such as `5m`, are not recorded by `timer idle watch`.
+    pub idle_threshold: String,
+    /// A file other tools touch while the user works, counted as activity.
+    pub idle_heartbeat: Option<PathBuf>,
+    /// Lengths of the `timer pomodoro` intervals, such as `25m`.
+    pub pomodoro_work: String,
+    pub pomodoro_short_break: String,
+    pub pomodoro_long_break: String,
+    /// Every this many pomodoros the break is a long one.
+    pub pomodoro_long_break_every: u32,
+    /// Shell command run when a pomodoro interval ends, such as `notify-send "$TIMER_MESSAGE"`.
+    pub pomodoro_notify: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            data_dir: None,
+            exclusive_start: false,
+            backup_count: 20,
+            backup_interval: String::from("0s"),
+            storage: StorageKind::Json,
+            max_session: String::from("12h"),
+            idle_threshold: String::from("5m"),
+            idle_heartbeat: None,
+            pomodoro_work: String::from("25m"),
+            pomodoro_short_break: String::from("5m"),
+            pomodoro_long_break: String::from("15m"),
+            pomodoro_long_break_every: 4,
+            pomodoro_notify: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn load() -> Result<Config, TimerError> {
+        let Some(config_path) = config_file_path() else {
+            return Ok(Config::default());
+        };
+        if !config_path.exists() {
+            return Ok(Config::default());
+        }
+        let contents = fs::read_to_string(&config_path).map_err(TimerError::io(&config_path))?;
+        serde_json::from_str(&contents).map_err(|err| TimerError::CorruptFile(config_path, err))
+    }
+}
+
+/// Reads a duration setting such as `max_session`.
+pub fn duration_setting(name: &str, value: &str) -> Result<Duration, TimerError> {
+    parse_time(value)
+        .map(Duration::from_secs)
+        .map_err(|_| TimerError::Config(format!("{name} '{value}' is not a duration such as 12h")))
+}
+
+fn env_path(name: &str) -> Option<PathBuf> {
+    env::var_os(name)
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+}
+
+fn home_dir() -> Option<PathBuf> {
+    env_path("HOME").or_else(|| env_path("USERPROFILE"))
+}
+
+/// `$XDG_CONFIG_HOME/simple-task-timer/config.json`, falling back to `~/.config`
+/// (`%APPDATA%` on Windows).
+pub fn config_file_path() -> Option<PathBuf> {
+    let base = if cfg!(windows) {
+        env_path("APPDATA")
+    } else {
+        env_path("XDG_CONFIG_HOME").or_else(|| home_dir().map(|home| home.join(".config")))
+    };
+    base.map(|dir| dir.join(APP_NAME).join("config.json"))
+}
+
+/// `$XDG_DATA_HOME/simple-task-timer`, falling back to `~/.local/share`
+/// (`%APPDATA%` on Windows).
+pub fn default_data_dir() -> Result<PathBuf, TimerError> {
+    let base = if cfg!(windows) {
+        env_path("APPDATA")
+    } else {
+        env_path("XDG_DATA_HOME").or_else(|| home_dir().map(|home| home.join(".local").join("share")))
+    };
+    base.map(|dir| dir.join(APP_NAME)).ok_or_else(|| {
+        TimerError::Config(String::from("Could not find the home directory, use --data-dir or TIMER_DATA_DIR"))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_durations_are_valid() {
+        let config = Config::default();
+        assert_eq!(Duration::ZERO, duration_setting("backup_interval", &config.backup_interval).unwrap());
+        for (name, value) in [("max_session", &config.max_session), ("idle_threshold", &config.idle_threshold)] {
+            assert!(duration_setting(name, value).is_ok());
+        }
+    }
+}
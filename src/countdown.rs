@@ -0,0 +1,71 @@
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use crossterm::cursor::MoveToColumn;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::style::Print;
+use crossterm::terminal::{self, Clear, ClearType};
+use crossterm::{execute, queue};
+
+use simple_task_timer::error::TimerError;
+use simple_task_timer::utils::format_duration;
+
+// Leaves raw mode and the countdown line even when the countdown returns with an error.
+struct RawModeGuard {
+    show: bool,
+}
+
+impl Drop for RawModeGuard {
+    fn drop(&mut self) {
+        let _ = terminal::disable_raw_mode();
+        if self.show {
+            let _ = execute!(io::stdout(), Print("\r\n"));
+        }
+    }
+}
+
+/// Waits for `length`, or until q or Ctrl-C is pressed, in which case false is returned.
+/// With `show`, the time left is counted down on one line after `label`.
+pub fn countdown(label: &str, length: Duration, show: bool) -> Result<bool, TimerError> {
+    let end = Instant::now() + length;
+    // In raw mode Ctrl-C arrives as a key instead of killing the process with the task running.
+    terminal::enable_raw_mode().map_err(TimerError::io("terminal"))?;
+    let _guard = RawModeGuard { show };
+    let mut stdout = io::stdout();
+    loop {
+        let left = end.saturating_duration_since(Instant::now());
+        // Rounded up, so the countdown shows 00:00:00 only once the time is over.
+        let seconds = left.as_secs() + u64::from(left.subsec_nanos() > 0);
+        if show {
+            queue!(
+                stdout,
+                MoveToColumn(0),
+                Clear(ClearType::CurrentLine),
+                Print(format!("{label}: {} left, q to stop", format_duration(seconds))),
+            )
+            .and_then(|_| stdout.flush())
+            .map_err(TimerError::io("terminal"))?;
+        }
+        if left.is_zero() {
+            return Ok(true);
+        }
+        // Wake up when the display changes to the next second.
+        let wait = match left.subsec_nanos() {
+            0 => Duration::from_secs(1),
+            nanos => Duration::new(0, nanos),
+        };
+        if !event::poll(wait).map_err(TimerError::io("terminal"))? {
+            continue;
+        }
+        if let Event::Key(key) = event::read().map_err(TimerError::io("terminal"))? {
+            let quit = match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => true,
+                KeyCode::Char('c') => key.modifiers.contains(KeyModifiers::CONTROL),
+                _ => false,
+            };
+            if key.kind == KeyEventKind::Press && quit {
+                return Ok(false);
+            }
+        }
+    }
+}
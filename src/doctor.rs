@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::time::SystemTime;
+
+use serde_json::{json, Value};
+
+use crate::error::TimerError;
+use crate::journal::{move_aside, Journal, Snapshot};
+use crate::storage::Storage;
+use crate::task::{normalize_project, normalize_tag, project_from_segments, tag_from_words, Task};
+
+/// A problem found in the data directory and how `--fix` repairs it, `None` when it cannot.
+pub struct Issue {
+    pub file: String,
+    pub id: Option<u32>,
+    pub problem: String,
+    pub repair: Option<&'static str>,
+}
+
+impl Issue {
+    fn new(file: &str, id: Option<u32>, problem: String, repair: Option<&'static str>) -> Issue {
+        Issue {
+            file: file.to_string(),
+            id,
+            problem,
+            repair,
+        }
+    }
+
+    pub fn to_print_string(&self) -> String {
+        let location = match self.id {
+            Some(id) => format!("{} [{id}]", self.file),
+            None => self.file.clone(),
+        };
+        let repair = self.repair.unwrap_or("cannot be repaired automatically");
+        format!("{location}: {}, {repair}", self.problem)
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({ "file": self.file, "id": self.id, "problem": self.problem, "repair": self.repair })
+    }
+}
+
+/// Finds inconsistent tasks in a list and repairs them in place, the caller decides
+/// whether to save the result.
+pub fn check_tasks(list: &str, tasks: &mut HashMap<u32, Task>, now: SystemTime) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    let mut ids: Vec<u32> = tasks.keys().copied().collect();
+    ids.sort();
+    for id in ids {
+        let task = tasks.get_mut(&id).unwrap();
+        let mut issue = |problem: String, repair| issues.push(Issue::new(list, Some(id), problem, Some(repair)));
+        if task.id != id {
+            issue(format!("is stored under id {id} but has id {}", task.id), "use the id it is stored under");
+            task.id = id;
+        }
+        match task.last_run {
+            None if task.running => {
+                issue(String::from("is running without a start time"), "mark it stopped");
+                task.running = false;
+            }
+            Some(last_run) if task.running && last_run > now => {
+                issue(String::from("was started in the future"), "start it now");
+                task.last_run = Some(now);
+            }
+            _ => {}
+        }
+        let sessions = task.sessions.len();
+        task.sessions.retain(|session| session.start <= session.end);
+        if task.sessions.len() < sessions {
+            issue(format!("has {} sessions ending before they start", sessions - task.sessions.len()), "remove them");
+        }
+        if task.tags.iter().any(|tag| normalize_tag(tag).ok().as_ref() != Some(tag)) {
+            issue(String::from("has invalid tags"), "replace spaces with dashes and drop empty tags");
+            task.tags = task.tags.iter().filter_map(|tag| tag_from_words(tag)).collect();
+        }
+        if let Some(project) = &task.project {
+            if normalize_project(project).ok().as_ref() != Some(project) {
+                issue(format!("has the invalid project '{project}'"), "drop empty segments and spaces");
+                task.project = project_from_segments(project.split('/'));
+            }
+        }
+    }
+    issues
+}
+
+/// Checks every task list and the journal as of `now`, repairing what can be repaired when
+/// `fix` is set.
+pub fn run_doctor(storage: &dyn Storage, fix: bool, command: &str, now: SystemTime) -> Result<Vec<Issue>, TimerError> {
+    let data_dir = storage.data_dir();
+    let mut issues = Vec::new();
+    // Checked first, the repairs of the task lists below are recorded in the journal.
+    if let Err(err) = Journal::read(data_dir) {
+        issues.push(Issue::new("journal.json", None, err.to_string(), Some("move it aside and start a new history")));
+        if fix {
+            move_aside(data_dir)?;
+        }
+    }
+    for list in storage.list_names()? {
+        let location = storage.location(&list);
+        let (version, mut tasks) = match storage.outdated_version(&list).and_then(|version| {
+            storage.load_tasks(&list).map(|tasks| (version, tasks))
+        }) {
+            Ok(loaded) => loaded,
+            Err(err) => {
+                issues.push(Issue::new(&location, None, err.to_string(), None));
+                continue;
+            }
+        };
+        let mut list_issues = Vec::new();
+        if let Some(version) = version {
+            let problem = format!("uses the old format version {version}");
+            list_issues.push(Issue::new(&location, None, problem, Some("upgrade it to the current format")));
+        }
+        // Repairs can remove tasks, their ids are saved too.
+        let mut ids: Vec<u32> = tasks.keys().copied().collect();
+        list_issues.extend(check_tasks(&location, &mut tasks, now));
+        if fix && !list_issues.is_empty() {
+            // Journaled, so the repairs can be undone like any other change.
+            let snapshot = Snapshot::take(storage, &[&list])?;
+            ids.extend(tasks.keys());
+            storage.save_changed(&list, &tasks, &ids, true)?;
+            snapshot.record(storage, command, now)?;
+        }
+        issues.extend(list_issues);
+    }
+    Ok(issues)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::ops::{Add, Sub};
+    use std::time::Duration;
+
+    use crate::task::Session;
+
+    #[test]
+    fn running_without_start_is_stopped() {
+        let mut task = Task::new(1, "my task");
+        task.running = true;
+        // Used to panic, it is now treated as stopped.
+        let now = SystemTime::now();
+        assert_eq!(0, task.current_duration(now));
+        let mut tasks = HashMap::from([(1, task)]);
+        let issues = check_tasks("current.json", &mut tasks, now);
+        assert_eq!(1, issues.len());
+        assert_eq!("current.json [1]: is running without a start time, mark it stopped", issues[0].to_print_string());
+        assert!(!tasks[&1].running);
+    }
+
+    #[test]
+    fn inconsistent_tasks_are_repaired() {
+        let now = SystemTime::now();
+        let mut task = Task::new(7, "my task");
+        task.running = true;
+        task.last_run = Some(now.add(Duration::new(3600, 0)));
+        task.sessions.push(Session { start: now, end: now.sub(Duration::new(60, 0)) });
+        task.tags.insert(String::from("code review"));
+        task.project = Some(String::from("acme//backend"));
+        let mut tasks = HashMap::from([(2, task)]);
+        let issues = check_tasks("current.json", &mut tasks, now);
+        assert_eq!(5, issues.len());
+        let task = &tasks[&2];
+        assert_eq!(2, task.id);
+        assert_eq!(Some(now), task.last_run);
+        assert!(task.sessions.is_empty());
+        assert!(task.tags.contains("code-review"));
+        assert_eq!(Some(String::from("acme/backend")), task.project);
+        assert!(check_tasks("current.json", &mut tasks, now).is_empty());
+    }
+}
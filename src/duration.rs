@@ -0,0 +1,290 @@
+use std::fmt;
+
+const MINUTE: f64 = 60.0;
+const HOUR: f64 = 60.0 * MINUTE;
+const DAY: f64 = 24.0 * HOUR;
+const WEEK: f64 = 7.0 * DAY;
+
+#[derive(Debug, PartialEq)]
+pub enum ParseDurationError {
+    Empty,
+    InvalidNumber(String),
+    MissingNumber(String),
+    MissingUnit(String),
+    UnknownUnit(String),
+    UnitOrder(String),
+    InvalidClock,
+    UnsupportedIsoUnit(char),
+    Overflow,
+}
+
+impl fmt::Display for ParseDurationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseDurationError::Empty => write!(f, "the duration is empty"),
+            ParseDurationError::InvalidNumber(number) => write!(f, "'{number}' is not a valid number"),
+            ParseDurationError::MissingNumber(unit) => write!(f, "'{unit}' is not preceded by a number"),
+            ParseDurationError::MissingUnit(number) => write!(f, "'{number}' is missing a unit (d, h, m or s)"),
+            ParseDurationError::UnknownUnit(unit) => write!(f, "'{unit}' is not a known unit (d, h, m or s)"),
+            ParseDurationError::UnitOrder(unit) => {
+                write!(f, "'{unit}' is repeated or comes after a smaller unit")
+            }
+            ParseDurationError::InvalidClock => write!(f, "expected H:MM or H:MM:SS"),
+            ParseDurationError::UnsupportedIsoUnit(unit) => {
+                write!(f, "'{unit}' is not supported in ISO 8601 durations, use weeks, days or smaller units")
+            }
+            ParseDurationError::Overflow => write!(f, "the duration is too large"),
+        }
+    }
+}
+
+impl std::error::Error for ParseDurationError {}
+
+/// Parses a duration into seconds, rounding fractions to the nearest second.
+///
+/// Accepted formats:
+/// - units from largest to smallest, optionally with decimals: `1h30m`, `90s`, `1.5h`, `2d 4h`
+/// - clock notation: `1:30` (hours and minutes), `1:30:15`
+/// - ISO 8601 durations: `PT1H30M`, `P1DT2H`, `P1W`
+pub fn parse_duration(input: &str) -> Result<u64, ParseDurationError> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Err(ParseDurationError::Empty);
+    }
+    let seconds = if let Some(iso) = input.strip_prefix(['P', 'p']) {
+        parse_iso(iso)?
+    } else if input.contains(':') {
+        parse_clock(input)?
+    } else {
+        parse_units(input)?
+    };
+    to_whole_seconds(seconds)
+}
+
+fn to_whole_seconds(seconds: f64) -> Result<u64, ParseDurationError> {
+    let rounded = seconds.round();
+    if !rounded.is_finite() || rounded >= u64::MAX as f64 {
+        return Err(ParseDurationError::Overflow);
+    }
+    Ok(rounded as u64)
+}
+
+fn parse_number(number: &str) -> Result<f64, ParseDurationError> {
+    let normalized = number.replace(',', ".");
+    let valid = !normalized.is_empty()
+        && normalized.chars().all(|c| c.is_ascii_digit() || c == '.')
+        && normalized.chars().filter(|c| *c == '.').count() <= 1
+        && normalized.chars().any(|c| c.is_ascii_digit());
+    if !valid {
+        return Err(ParseDurationError::InvalidNumber(String::from(number)));
+    }
+    normalized
+        .parse::<f64>()
+        .map_err(|_| ParseDurationError::InvalidNumber(String::from(number)))
+}
+
+/// Splits `1h 30m` into `[("1", "h"), ("30", "m")]`. Numbers may contain `.` or `,`.
+fn components(input: &str) -> Result<Vec<(&str, &str)>, ParseDurationError> {
+    let mut components = Vec::new();
+    let mut rest = input.trim_start();
+    while !rest.is_empty() {
+        let number_end = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == ','))
+            .unwrap_or(rest.len());
+        let (number, after_number) = rest.split_at(number_end);
+        let after_number = after_number.trim_start();
+        let unit_end = after_number
+            .find(|c: char| !c.is_alphabetic())
+            .unwrap_or(after_number.len());
+        let (unit, after_unit) = after_number.split_at(unit_end);
+        if number.is_empty() {
+            let unexpected = if unit.is_empty() {
+                after_number.chars().next().map(String::from).unwrap_or_default()
+            } else {
+                String::from(unit)
+            };
+            return Err(ParseDurationError::MissingNumber(unexpected));
+        }
+        if unit.is_empty() {
+            return Err(ParseDurationError::MissingUnit(String::from(number)));
+        }
+        components.push((number, unit));
+        rest = after_unit.trim_start();
+    }
+    Ok(components)
+}
+
+fn unit_seconds(unit: &str) -> Option<f64> {
+    match unit.to_ascii_lowercase().as_str() {
+        "w" | "wk" | "week" | "weeks" => Some(WEEK),
+        "d" | "day" | "days" => Some(DAY),
+        "h" | "hr" | "hrs" | "hour" | "hours" => Some(HOUR),
+        "m" | "min" | "mins" | "minute" | "minutes" => Some(MINUTE),
+        "s" | "sec" | "secs" | "second" | "seconds" => Some(1.0),
+        _ => None,
+    }
+}
+
+fn parse_units(input: &str) -> Result<f64, ParseDurationError> {
+    let mut total = 0.0;
+    let mut previous_unit = f64::INFINITY;
+    for (number, unit) in components(input)? {
+        let seconds = unit_seconds(unit).ok_or_else(|| ParseDurationError::UnknownUnit(String::from(unit)))?;
+        if seconds >= previous_unit {
+            return Err(ParseDurationError::UnitOrder(String::from(unit)));
+        }
+        previous_unit = seconds;
+        total += parse_number(number)? * seconds;
+    }
+    Ok(total)
+}
+
+fn parse_clock(input: &str) -> Result<f64, ParseDurationError> {
+    let parts: Vec<&str> = input.split(':').collect();
+    if parts.len() > 3 || parts.iter().any(|part| part.is_empty() || !part.chars().all(|c| c.is_ascii_digit())) {
+        return Err(ParseDurationError::InvalidClock);
+    }
+    let values: Vec<f64> = parts.iter().map(|part| part.parse::<f64>().unwrap_or_default()).collect();
+    if values[1..].iter().any(|value| *value >= 60.0) {
+        return Err(ParseDurationError::InvalidClock);
+    }
+    let seconds = values.get(2).copied().unwrap_or_default();
+    Ok(values[0] * HOUR + values[1] * MINUTE + seconds)
+}
+
+// `input` is everything after the leading P.
+fn parse_iso(input: &str) -> Result<f64, ParseDurationError> {
+    let upper = input.to_ascii_uppercase();
+    let (date, time) = match upper.split_once('T') {
+        Some((date, time)) => (date, Some(time)),
+        None => (upper.as_str(), None),
+    };
+    if date.is_empty() && time.is_none_or(str::is_empty) {
+        return Err(ParseDurationError::Empty);
+    }
+    let mut total = 0.0;
+    for (part, units) in [(date, "WD"), (time.unwrap_or_default(), "HMS")] {
+        let mut previous_index = None;
+        for (number, unit) in components(part)? {
+            let designator = unit.chars().next().unwrap_or_default();
+            let index = match units.find(designator) {
+                Some(index) if unit.len() == 1 => index,
+                _ if unit == "Y" || (unit == "M" && units == "WD") => {
+                    return Err(ParseDurationError::UnsupportedIsoUnit(designator))
+                }
+                _ => return Err(ParseDurationError::UnknownUnit(String::from(unit))),
+            };
+            if previous_index.is_some_and(|previous| index <= previous) {
+                return Err(ParseDurationError::UnitOrder(String::from(unit)));
+            }
+            previous_index = Some(index);
+            let seconds = match designator {
+                'W' => WEEK,
+                'D' => DAY,
+                'H' => HOUR,
+                'M' => MINUTE,
+                _ => 1.0,
+            };
+            total += parse_number(number)? * seconds;
+        }
+    }
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> u64 {
+        parse_duration(input).unwrap()
+    }
+
+    #[test]
+    fn hours_and_minutes() {
+        assert_eq!(5400, parse("1h30m"));
+        assert_eq!(3600, parse("1h"));
+        assert_eq!(300, parse("5m"));
+        assert_eq!(164400, parse("45h40m"));
+        assert_eq!(0, parse("0m"));
+    }
+
+    #[test]
+    fn seconds_days_and_weeks() {
+        assert_eq!(90, parse("90s"));
+        assert_eq!(172800, parse("2d"));
+        assert_eq!(93784, parse("1d2h3m4s"));
+        assert_eq!(604800, parse("1w"));
+    }
+
+    #[test]
+    fn decimals() {
+        assert_eq!(5400, parse("1.5h"));
+        assert_eq!(5400, parse("1,5h"));
+        assert_eq!(43200, parse("0.5d"));
+        assert_eq!(45, parse("0.75m"));
+        assert_eq!(1, parse("0.6s"));
+        assert_eq!(1800, parse(".5h"));
+    }
+
+    #[test]
+    fn spaces_long_units_and_case() {
+        assert_eq!(5400, parse(" 1h 30m "));
+        assert_eq!(5400, parse("1 hour 30 minutes"));
+        assert_eq!(7290, parse("2hrs 1min 30sec"));
+        assert_eq!(5400, parse("1H30M"));
+    }
+
+    #[test]
+    fn clock_notation() {
+        assert_eq!(5400, parse("1:30"));
+        assert_eq!(5415, parse("1:30:15"));
+        assert_eq!(300, parse("0:05"));
+        assert_eq!(90000, parse("25:00"));
+    }
+
+    #[test]
+    fn iso_8601() {
+        assert_eq!(5400, parse("PT1H30M"));
+        assert_eq!(93600, parse("P1DT2H"));
+        assert_eq!(172800, parse("P2D"));
+        assert_eq!(1209600, parse("P2W"));
+        assert_eq!(90, parse("PT90S"));
+        assert_eq!(1800, parse("PT0.5H"));
+        assert_eq!(5400, parse("pt1h30m"));
+    }
+
+    #[test]
+    fn invalid_inputs() {
+        assert_eq!(Err(ParseDurationError::Empty), parse_duration(""));
+        assert_eq!(Err(ParseDurationError::Empty), parse_duration("   "));
+        assert_eq!(Err(ParseDurationError::MissingUnit(String::from("90"))), parse_duration("90"));
+        assert_eq!(Err(ParseDurationError::MissingNumber(String::from("m"))), parse_duration("m1h"));
+        assert_eq!(Err(ParseDurationError::UnknownUnit(String::from("hh"))), parse_duration("1hh"));
+        assert_eq!(Err(ParseDurationError::UnknownUnit(String::from("x"))), parse_duration("1x"));
+        assert_eq!(Err(ParseDurationError::UnitOrder(String::from("h"))), parse_duration("30m1h"));
+        assert_eq!(Err(ParseDurationError::UnitOrder(String::from("h"))), parse_duration("1h2h"));
+        assert_eq!(Err(ParseDurationError::InvalidNumber(String::from("1.2.3"))), parse_duration("1.2.3h"));
+        assert_eq!(Err(ParseDurationError::InvalidNumber(String::from("."))), parse_duration(".h"));
+        assert_eq!(Err(ParseDurationError::MissingNumber(String::from("-"))), parse_duration("-1h"));
+    }
+
+    #[test]
+    fn invalid_clock_and_iso() {
+        assert_eq!(Err(ParseDurationError::InvalidClock), parse_duration("1:60"));
+        assert_eq!(Err(ParseDurationError::InvalidClock), parse_duration("1:"));
+        assert_eq!(Err(ParseDurationError::InvalidClock), parse_duration("1:2:3:4"));
+        assert_eq!(Err(ParseDurationError::InvalidClock), parse_duration("1h:30"));
+        assert_eq!(Err(ParseDurationError::Empty), parse_duration("PT"));
+        assert_eq!(Err(ParseDurationError::Empty), parse_duration("P"));
+        assert_eq!(Err(ParseDurationError::UnsupportedIsoUnit('Y')), parse_duration("P1Y"));
+        assert_eq!(Err(ParseDurationError::UnsupportedIsoUnit('M')), parse_duration("P1M"));
+        assert_eq!(Err(ParseDurationError::UnitOrder(String::from("H"))), parse_duration("PT1M1H"));
+        assert_eq!(Err(ParseDurationError::UnknownUnit(String::from("D"))), parse_duration("PT1D"));
+        assert_eq!(Err(ParseDurationError::MissingUnit(String::from("1"))), parse_duration("PT1"));
+    }
+
+    #[test]
+    fn overflow() {
+        assert_eq!(Err(ParseDurationError::Overflow), parse_duration("99999999999999999999999d"));
+    }
+}
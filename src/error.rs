@@ -0,0 +1,134 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use crate::duration::ParseDurationError;
+
+#[derive(Debug)]
+pub enum TimerError {
+    InvalidArgument(String),
+    InvalidTime(String, ParseDurationError),
+    InvalidTimestamp(String),
+    TaskNotFound(u32),
+    TaskNameNotFound(String),
+    BackupNotFound(String),
+    AmbiguousTaskName(String),
+    AlreadyRunning(u32),
+    NotRunning(u32),
+    TaskRunning(u32, &'static str),
+    NotEnoughTime(u32),
+    EmptyHistory(&'static str),
+    HistoryConflict(u32, String),
+    Io(PathBuf, io::Error),
+    // Only returned by the SQLite storage.
+    #[cfg_attr(not(feature = "sqlite"), allow(dead_code))]
+    Database(PathBuf, String),
+    CorruptFile(PathBuf, serde_json::Error),
+    InvalidImport(PathBuf, String),
+    UnsupportedVersion(PathBuf, u64),
+    Config(String),
+}
+
+impl TimerError {
+    /// Process exit code, one per class of error so scripts can tell them apart.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            TimerError::InvalidArgument(_) | TimerError::InvalidTime(_, _) | TimerError::InvalidTimestamp(_) => 2,
+            TimerError::TaskNotFound(_) | TimerError::TaskNameNotFound(_) | TimerError::BackupNotFound(_) => 3,
+            TimerError::AmbiguousTaskName(_)
+            | TimerError::AlreadyRunning(_)
+            | TimerError::NotRunning(_)
+            | TimerError::TaskRunning(_, _)
+            | TimerError::NotEnoughTime(_)
+            | TimerError::EmptyHistory(_)
+            | TimerError::HistoryConflict(_, _) => 4,
+            TimerError::Io(_, _) | TimerError::Database(_, _) => 5,
+            TimerError::CorruptFile(_, _)
+            | TimerError::InvalidImport(_, _)
+            | TimerError::UnsupportedVersion(_, _)
+            | TimerError::Config(_) => 6,
+        }
+    }
+
+    /// Stable identifier of the error variant, used in JSON output.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            TimerError::InvalidArgument(_) => "invalid_argument",
+            TimerError::InvalidTime(_, _) => "invalid_time",
+            TimerError::InvalidTimestamp(_) => "invalid_timestamp",
+            TimerError::TaskNotFound(_) => "task_not_found",
+            TimerError::TaskNameNotFound(_) => "task_name_not_found",
+            TimerError::BackupNotFound(_) => "backup_not_found",
+            TimerError::AmbiguousTaskName(_) => "ambiguous_task_name",
+            TimerError::AlreadyRunning(_) => "already_running",
+            TimerError::NotRunning(_) => "not_running",
+            TimerError::TaskRunning(_, _) => "task_running",
+            TimerError::NotEnoughTime(_) => "not_enough_time",
+            TimerError::EmptyHistory(_) => "empty_history",
+            TimerError::HistoryConflict(_, _) => "history_conflict",
+            TimerError::Io(_, _) => "io",
+            TimerError::Database(_, _) => "database",
+            TimerError::CorruptFile(_, _) => "corrupt_file",
+            TimerError::InvalidImport(_, _) => "invalid_import",
+            TimerError::UnsupportedVersion(_, _) => "unsupported_version",
+            TimerError::Config(_) => "config",
+        }
+    }
+
+    pub fn io(path: impl Into<PathBuf>) -> impl FnOnce(io::Error) -> TimerError {
+        let path = path.into();
+        move |err| TimerError::Io(path, err)
+    }
+}
+
+impl fmt::Display for TimerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimerError::InvalidArgument(message) => write!(f, "{message}"),
+            TimerError::InvalidTime(time, err) => {
+                write!(f, "Could not parse time '{time}': {err}. Examples: 1h30m, 90s, 1.5h, 2d, 1:30, PT1H30M")
+            }
+            TimerError::InvalidTimestamp(time) => write!(
+                f,
+                "Could not parse timestamp '{time}'. Examples: 09:15, yesterday 17:30, 20 minutes ago, 2024-03-04 09:15"
+            ),
+            TimerError::TaskNotFound(id) => write!(f, "Task with id {id} does not exist"),
+            TimerError::TaskNameNotFound(name) => write!(f, "Task with name {name} does not exist"),
+            TimerError::BackupNotFound(name) => write!(f, "Backup {name} does not exist, see timer backup list"),
+            TimerError::AmbiguousTaskName(name) => write!(f, "More than one task with name {name}"),
+            TimerError::AlreadyRunning(id) => write!(f, "Task {id} is already running"),
+            TimerError::NotRunning(id) => write!(f, "Task {id} is not currently running"),
+            TimerError::TaskRunning(id, action) => {
+                write!(f, "Task {id} is currently running, stop it before {action}")
+            }
+            TimerError::NotEnoughTime(id) => write!(f, "Task {id} does not have enough time to subtract"),
+            TimerError::EmptyHistory(action) => write!(f, "Nothing to {action}"),
+            TimerError::HistoryConflict(id, list) => {
+                write!(f, "Task {id} in {list} was changed outside of the history, it cannot be restored")
+            }
+            TimerError::Io(path, err) => write!(f, "Could not access {}: {err}", path.display()),
+            TimerError::Database(path, message) => {
+                write!(f, "Could not access the database {}: {message}", path.display())
+            }
+            TimerError::CorruptFile(path, err) => write!(f, "Could not parse {}: {err}", path.display()),
+            TimerError::InvalidImport(path, message) => write!(f, "Could not import {}: {message}", path.display()),
+            TimerError::UnsupportedVersion(path, version) => write!(
+                f,
+                "{} has version {version}, which this version of timer cannot read, please upgrade",
+                path.display()
+            ),
+            TimerError::Config(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for TimerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TimerError::Io(_, err) => Some(err),
+            TimerError::CorruptFile(_, err) => Some(err),
+            TimerError::InvalidTime(_, err) => Some(err),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::time::SystemTime;
+
+use chrono::{DateTime, Local};
+
+use crate::report::DateRange;
+use crate::task::{Task, TaskFilter};
+use crate::utils::format_signed_duration;
+
+const CSV_HEADER: [&str; 9] = ["list", "id", "name", "project", "tags", "start", "end", "duration_seconds", "duration"];
+
+/// One line of the timesheet: a session, or time of a task that has no date.
+pub struct ExportRow<'a> {
+    pub list: &'a str,
+    pub task: &'a Task,
+    pub start: Option<SystemTime>,
+    /// `None` for the session of a running task, which is counted until now.
+    pub end: Option<SystemTime>,
+    pub seconds: i64,
+}
+
+/// Rows for every session of the matching tasks that starts inside `range`. Time not
+/// backed by a session (legacy totals and manual adjustments) gets a row without start
+/// and end, only when the range is unbounded since it cannot be placed on a date.
+pub fn export_rows<'a>(
+    lists: &'a [(&'a str, HashMap<u32, Task>)],
+    range: DateRange,
+    filter: &TaskFilter,
+    now: SystemTime,
+) -> Vec<ExportRow<'a>> {
+    let unbounded = range.from.is_none() && range.to.is_none();
+    let mut rows = Vec::new();
+    for (list, tasks) in lists {
+        let mut ids: Vec<&u32> = tasks.keys().collect();
+        ids.sort();
+        for task in ids.into_iter().map(|id| &tasks[id]).filter(|task| filter.matches(task)) {
+            let finished = task.sessions.iter().map(|session| (session.clone(), Some(session.end)));
+            let running = task.running_session(now).map(|session| (session, None));
+            for (session, end) in finished.chain(running) {
+                if !range.contains(DateTime::<Local>::from(session.start).date_naive()) {
+                    continue;
+                }
+                rows.push(ExportRow {
+                    list,
+                    task,
+                    start: Some(session.start),
+                    end,
+                    seconds: session.duration() as i64,
+                });
+            }
+            if unbounded && (task.adjustment_seconds != 0 || (task.sessions.is_empty() && !task.running)) {
+                rows.push(ExportRow {
+                    list,
+                    task,
+                    start: None,
+                    end: None,
+                    seconds: task.adjustment_seconds,
+                });
+            }
+        }
+    }
+    rows
+}
+
+fn format_time(time: Option<SystemTime>) -> String {
+    time.map_or(String::new(), |time| DateTime::<Local>::from(time).to_rfc3339())
+}
+
+/// Writes the rows with the `csv` crate, which quotes fields the same way `timer import` reads them.
+pub fn write_csv(writer: &mut impl Write, rows: &[ExportRow]) -> io::Result<()> {
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record(CSV_HEADER)?;
+    for row in rows {
+        let tags: Vec<&str> = row.task.tags.iter().map(String::as_str).collect();
+        writer.write_record([
+            row.list.to_string(),
+            row.task.id.to_string(),
+            row.task.name.clone(),
+            row.task.project.clone().unwrap_or_default(),
+            tags.join(" "),
+            format_time(row.start),
+            format_time(row.end),
+            row.seconds.to_string(),
+            format_signed_duration(row.seconds),
+        ])?;
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::ops::Sub;
+    use std::time::Duration;
+
+    use chrono::{NaiveDate, TimeZone};
+
+    use crate::task::Session;
+
+    fn local(y: i32, m: u32, d: u32, h: u32, min: u32) -> SystemTime {
+        Local.with_ymd_and_hms(y, m, d, h, min, 0).unwrap().into()
+    }
+
+    fn csv(rows: &[ExportRow]) -> String {
+        let mut buffer = Vec::new();
+        write_csv(&mut buffer, rows).unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    #[test]
+    fn fields_are_quoted() {
+        let mut task = Task::new(1, "say \"hi\"");
+        task.project = Some(String::from("a, b"));
+        task.adjustment_seconds = 60;
+        let lists = [("plain", HashMap::from([(1, task)]))];
+        let rows = export_rows(&lists, DateRange::default(), &TaskFilter::default(), SystemTime::now());
+        let output = csv(&rows);
+        assert_eq!("plain,1,\"say \"\"hi\"\"\",\"a, b\",,,,60,00:01:00", output.lines().nth(1).unwrap());
+    }
+
+    #[test]
+    fn one_row_per_session_or_task() {
+        let mut with_sessions = Task::new(1, "billing, march");
+        with_sessions.sessions.push(Session { start: local(2024, 3, 4, 9, 0), end: local(2024, 3, 4, 10, 30) });
+        with_sessions.sessions.push(Session { start: local(2024, 3, 5, 9, 0), end: local(2024, 3, 5, 9, 30) });
+        let mut legacy = Task::new(1, "old");
+        legacy.adjustment_seconds = 60;
+        let lists = [
+            ("current", HashMap::from([(1, with_sessions)])),
+            ("archive", HashMap::from([(1, legacy)])),
+        ];
+        let now = SystemTime::now();
+
+        let rows = export_rows(&lists, DateRange::default(), &TaskFilter::default(), now);
+        let output = csv(&rows);
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(4, lines.len());
+        assert_eq!("list,id,name,project,tags,start,end,duration_seconds,duration", lines[0]);
+        assert!(lines[1].starts_with("current,1,\"billing, march\",,,2024-03-04T09:00:00"));
+        assert!(lines[1].ends_with(",5400,01:30:00"));
+        assert_eq!("archive,1,old,,,,,60,00:01:00", lines[3]);
+
+        let day = NaiveDate::from_ymd_opt(2024, 3, 5);
+        let range = DateRange { from: day, to: day };
+        let rows = export_rows(&lists, range, &TaskFilter::default(), now);
+        assert_eq!(1, rows.len());
+        assert_eq!(1800, rows[0].seconds);
+    }
+
+    #[test]
+    fn running_session_has_no_end() {
+        let now = SystemTime::now();
+        let mut task = Task::new(1, "my task");
+        task.running = true;
+        task.last_run = Some(now.sub(Duration::new(90, 0)));
+        let lists = [("current", HashMap::from([(1, task)]))];
+        let rows = export_rows(&lists, DateRange::default(), &TaskFilter::default(), now);
+        assert_eq!(1, rows.len());
+        assert_eq!(None, rows[0].end);
+        assert_eq!(90, rows[0].seconds);
+    }
+}
@@ -0,0 +1,224 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use crate::activity::last_activity;
+use crate::error::TimerError;
+use crate::persistence::write_json;
+use crate::task::Session;
+
+// Periods nobody reviewed are forgotten after a month.
+const KEEP_PERIODS: Duration = Duration::from_secs(30 * 24 * 3600);
+
+/// What counts as being away, from the config file.
+#[derive(Clone, Debug)]
+pub struct IdleSettings {
+    /// Shorter breaks are not recorded.
+    pub threshold: Duration,
+    /// A file touched by other tools while the user works, such as an editor plugin.
+    pub heartbeat: Option<PathBuf>,
+}
+
+impl Default for IdleSettings {
+    fn default() -> IdleSettings {
+        IdleSettings {
+            threshold: Duration::from_secs(5 * 60),
+            heartbeat: None,
+        }
+    }
+}
+
+/// What to do with the time a task ran while the user was away.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IdleDecision {
+    Discard,
+    Keep,
+    /// Move the time to another task of the list.
+    Reassign(u32),
+}
+
+/// An idle period and the tasks of a list that ran during it, with how long each did.
+#[derive(Clone, Debug)]
+pub struct IdleReview {
+    pub period: Session,
+    pub tasks: Vec<(u32, u64)>,
+}
+
+/// A period without activity, with the lists whose tasks were already reviewed for it.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct IdlePeriod {
+    #[serde(flatten)]
+    pub session: Session,
+    #[serde(default)]
+    pub reviewed: Vec<String>,
+}
+
+/// Periods without activity noticed by `timer idle watch` while tasks ran, kept in
+/// `idle.json` in the data directory until every list that ran during them is reviewed.
+#[derive(Serialize, Deserialize, Default)]
+pub struct IdleLog {
+    pub periods: Vec<IdlePeriod>,
+}
+
+fn idle_path(data_dir: &Path) -> PathBuf {
+    data_dir.join("idle.json")
+}
+
+impl IdleLog {
+    pub fn load(data_dir: &Path) -> Result<IdleLog, TimerError> {
+        let path = idle_path(data_dir);
+        if !path.exists() {
+            return Ok(IdleLog::default());
+        }
+        let contents = fs::read_to_string(&path).map_err(TimerError::io(&path))?;
+        serde_json::from_str(&contents).map_err(|err| TimerError::CorruptFile(path, err))
+    }
+
+    pub fn save(&self, data_dir: &Path) -> Result<(), TimerError> {
+        write_json(data_dir, &idle_path(data_dir), self)
+    }
+
+    /// Notes that there was no activity for `idle` up to `now` and returns the period it
+    /// belongs to. Samples of the same break extend one period.
+    pub fn record(&mut self, idle: Duration, now: SystemTime) -> Session {
+        let start = now.checked_sub(idle).unwrap_or(now);
+        self.periods.retain(|period| period.session.end + KEEP_PERIODS > now);
+        match self.periods.last_mut() {
+            Some(last) if last.session.end >= start => {
+                last.session.start = last.session.start.min(start);
+                last.session.end = last.session.end.max(now);
+                // A longer period needs another look, even from lists that reviewed it.
+                last.reviewed.clear();
+            }
+            _ => self.periods.push(IdlePeriod { session: Session { start, end: now }, reviewed: Vec::new() }),
+        }
+        self.periods[self.periods.len() - 1].session.clone()
+    }
+
+    /// Notes that the tasks of `list` were reviewed for `period` and returns every list
+    /// reviewed so far.
+    pub fn mark_reviewed(&mut self, period: &Session, list: &str) -> Vec<String> {
+        let mut reviewed = vec![list.to_string()];
+        for other in self.periods.iter_mut().filter(|other| same_period(&other.session, period)) {
+            if !other.reviewed.iter().any(|name| name == list) {
+                other.reviewed.push(list.to_string());
+            }
+            reviewed.clone_from(&other.reviewed);
+        }
+        reviewed
+    }
+
+    pub fn remove(&mut self, period: &Session) {
+        self.periods.retain(|other| !same_period(&other.session, period));
+    }
+}
+
+fn same_period(one: &Session, other: &Session) -> bool {
+    one.start == other.start && one.end == other.end
+}
+
+/// Time since the last sign of activity: input on the desktop, a touch of the heartbeat
+/// file or a change made with `timer`, whichever is the most recent. `None` without any signal.
+pub fn idle_time(data_dir: &Path, settings: &IdleSettings, now: SystemTime) -> Result<Option<Duration>, TimerError> {
+    let mut signals = vec![desktop_idle_time()];
+    if let Some(heartbeat) = &settings.heartbeat {
+        let modified = fs::metadata(heartbeat).and_then(|metadata| metadata.modified()).ok();
+        signals.push(modified.map(|modified| now.duration_since(modified).unwrap_or_default()));
+    }
+    let last_used = last_activity(data_dir)?;
+    signals.push(last_used.map(|last_used| now.duration_since(last_used).unwrap_or_default()));
+    Ok(signals.into_iter().flatten().min())
+}
+
+// X11 through xprintidle, then GNOME on Wayland through its idle monitor. Both report
+// milliseconds since the last input.
+fn desktop_idle_time() -> Option<Duration> {
+    if let Some(output) = run("xprintidle", &[]) {
+        return parse_xprintidle(&output);
+    }
+    let output = run(
+        "gdbus",
+        &[
+            "call",
+            "--session",
+            "--dest",
+            "org.gnome.Mutter.IdleMonitor",
+            "--object-path",
+            "/org/gnome/Mutter/IdleMonitor/Core",
+            "--method",
+            "org.gnome.Mutter.IdleMonitor.GetIdletime",
+        ],
+    )?;
+    parse_mutter_idle(&output)
+}
+
+fn run(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    output.status.success().then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn parse_xprintidle(output: &str) -> Option<Duration> {
+    output.trim().parse().ok().map(Duration::from_millis)
+}
+
+// Replies look like `(uint64 52017,)`.
+fn parse_mutter_idle(output: &str) -> Option<Duration> {
+    let value = output.trim().strip_prefix("(uint64 ")?.strip_suffix(",)")?;
+    value.parse().ok().map(Duration::from_millis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(minutes: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000 + minutes * 60)
+    }
+
+    fn minutes(minutes: u64) -> Duration {
+        Duration::from_secs(minutes * 60)
+    }
+
+    #[test]
+    fn samples_of_one_break_make_one_period() {
+        let mut log = IdleLog::default();
+        log.record(minutes(5), at(10));
+        log.record(minutes(6), at(11));
+        let period = log.record(minutes(40), at(45));
+        assert_eq!((at(5), at(45)), (period.start, period.end));
+        // Back at the desk at 50, away again from 60.
+        log.record(minutes(5), at(65));
+        assert_eq!(2, log.periods.len());
+        log.remove(&period);
+        assert_eq!(at(60), log.periods[0].session.start);
+    }
+
+    #[test]
+    fn reviews_are_forgotten_when_the_period_grows() {
+        let mut log = IdleLog::default();
+        let period = log.record(minutes(5), at(10));
+        log.mark_reviewed(&period, "current");
+        log.mark_reviewed(&period, "current");
+        assert_eq!(vec![String::from("current")], log.periods[0].reviewed);
+        log.record(minutes(10), at(15));
+        assert!(log.periods[0].reviewed.is_empty());
+    }
+
+    #[test]
+    fn old_periods_are_forgotten() {
+        let mut log = IdleLog::default();
+        log.record(minutes(5), at(10));
+        log.record(minutes(5), at(10) + KEEP_PERIODS + minutes(60));
+        assert_eq!(1, log.periods.len());
+    }
+
+    #[test]
+    fn desktop_idle_replies_are_parsed() {
+        assert_eq!(Some(Duration::from_millis(52017)), parse_xprintidle("52017\n"));
+        assert_eq!(Some(Duration::from_millis(52017)), parse_mutter_idle("(uint64 52017,)\n"));
+        assert_eq!(None, parse_mutter_idle("Error: no such interface"));
+    }
+}
@@ -0,0 +1,397 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use chrono::{Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use serde_json::Value;
+
+use crate::error::TimerError;
+use crate::store::find_new_unique_id;
+use crate::task::{project_from_segments, tag_from_words, Session, Task};
+
+const DATE_FORMATS: [&str; 3] = ["%Y-%m-%d", "%m/%d/%Y", "%d.%m.%Y"];
+const TIME_FORMATS: [&str; 4] = ["%H:%M:%S", "%H:%M", "%I:%M:%S %p", "%I:%M %p"];
+const UNTITLED: &str = "Untitled";
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ImportSource {
+    Toggl,
+    Clockify,
+    Watson,
+    Timewarrior,
+}
+
+impl ImportSource {
+    pub fn parse(value: &str) -> Option<ImportSource> {
+        match value {
+            "toggl" => Some(ImportSource::Toggl),
+            "clockify" => Some(ImportSource::Clockify),
+            "watson" => Some(ImportSource::Watson),
+            "timewarrior" => Some(ImportSource::Timewarrior),
+            _ => None,
+        }
+    }
+}
+
+/// A time entry read from another tracker. Entries with the same name and project
+/// become the sessions of one task.
+#[derive(Debug, PartialEq)]
+pub struct Entry {
+    pub name: String,
+    pub project: Option<String>,
+    pub tags: BTreeSet<String>,
+    pub start: SystemTime,
+    /// `None` while the entry is still being tracked.
+    pub end: Option<SystemTime>,
+    /// The file the entry was read from and where it is in it, such as `on line 3`, for errors.
+    pub file: PathBuf,
+    pub location: String,
+}
+
+pub fn read_entries(source: ImportSource, path: &Path) -> Result<Vec<Entry>, TimerError> {
+    let contents = fs::read_to_string(path).map_err(TimerError::io(path))?;
+    let entries = match source {
+        ImportSource::Toggl | ImportSource::Clockify => parse_csv(&contents),
+        ImportSource::Watson => parse_watson(&contents),
+        ImportSource::Timewarrior => parse_timewarrior(&contents),
+    };
+    let mut entries = entries.map_err(|message| TimerError::InvalidImport(path.to_path_buf(), message))?;
+    for entry in &mut entries {
+        entry.file = path.to_path_buf();
+    }
+    Ok(entries)
+}
+
+/// Adds the entries to `tasks` as new tasks and returns their ids. Entries ending before
+/// they start, overlapping entries of one task and, with `exclusive_start`, open entries
+/// that would run alongside another task are rejected with the line they are on.
+pub fn import_entries(
+    tasks: &mut HashMap<u32, Task>,
+    entries: Vec<Entry>,
+    exclusive_start: bool,
+    now: SystemTime,
+) -> Result<Vec<u32>, TimerError> {
+    let invalid = |entry: &Entry, problem: &str| {
+        TimerError::InvalidImport(entry.file.clone(), format!("{problem} {}", entry.location))
+    };
+    let mut groups: Vec<Vec<Entry>> = Vec::new();
+    for entry in entries {
+        if entry.end.is_some_and(|end| end < entry.start) {
+            return Err(invalid(&entry, "entry ends before it starts"));
+        }
+        match groups
+            .iter_mut()
+            .find(|group| group[0].name == entry.name && group[0].project == entry.project)
+        {
+            Some(group) => group.push(entry),
+            None => groups.push(vec![entry]),
+        }
+    }
+    let mut running = tasks.values().any(|task| task.running);
+    for group in &mut groups {
+        group.sort_by_key(|entry| entry.start);
+        for pair in group.windows(2) {
+            if pair[1].start < pair[0].end.unwrap_or(now) {
+                return Err(invalid(&pair[1], "entry overlaps another entry of the same task"));
+            }
+        }
+        if let Some(open) = group.iter().find(|entry| entry.end.is_none()) {
+            if exclusive_start && running {
+                return Err(invalid(open, "another task is running and exclusive_start allows no second open entry"));
+            }
+            running = true;
+        }
+    }
+
+    let mut ids = Vec::new();
+    for group in groups {
+        let mut task = Task::new(find_new_unique_id(tasks), &group[0].name);
+        task.project = group[0].project.clone();
+        for entry in group {
+            task.tags.extend(entry.tags);
+            match entry.end {
+                Some(end) => task.sessions.push(Session { start: entry.start, end }),
+                None => {
+                    task.running = true;
+                    task.last_run = Some(entry.start);
+                }
+            }
+        }
+        ids.push(task.id);
+        tasks.insert(task.id, task);
+    }
+    Ok(ids)
+}
+
+// Joins client and project into a `client/project` path, slashes inside a name would
+// otherwise add levels to the hierarchy.
+fn import_project(parts: &[&str]) -> Option<String> {
+    project_from_segments(parts.iter().map(|part| part.replace('/', "-")))
+}
+
+fn parse_date_time(date: &str, time: &str) -> Option<SystemTime> {
+    let date = DATE_FORMATS
+        .iter()
+        .find_map(|format| NaiveDate::parse_from_str(date.trim(), format).ok())?;
+    let time = TIME_FORMATS
+        .iter()
+        .find_map(|format| NaiveTime::parse_from_str(time.trim(), format).ok())?;
+    Local.from_local_datetime(&date.and_time(time)).earliest().map(SystemTime::from)
+}
+
+/// Reads the detailed CSV export of Toggl Track or Clockify, both name their columns
+/// Description, Project, Client, Tags, Start date, Start time, End date and End time.
+fn parse_csv(contents: &str) -> Result<Vec<Entry>, String> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(contents.as_bytes());
+    let headers: Vec<String> = reader
+        .headers()
+        .map_err(|err| err.to_string())?
+        .iter()
+        .map(|header| header.trim().trim_start_matches('\u{feff}').to_lowercase())
+        .collect();
+    let column = |name: &str| headers.iter().position(|header| header == name);
+    let required = |name: &str| column(name).ok_or_else(|| format!("missing the '{name}' column"));
+    let (start_date, start_time) = (required("start date")?, required("start time")?);
+    let (end_date, end_time) = (required("end date")?, required("end time")?);
+    let (description, task, project, client, tags) =
+        (column("description"), column("task"), column("project"), column("client"), column("tags"));
+
+    let mut entries = Vec::new();
+    for (index, record) in reader.records().enumerate() {
+        let line = index + 2;
+        let record = record.map_err(|err| err.to_string())?;
+        let field = |column: Option<usize>| column.and_then(|column| record.get(column)).unwrap_or_default().trim();
+        let start = parse_date_time(field(Some(start_date)), field(Some(start_time)))
+            .ok_or_else(|| format!("invalid start on line {line}"))?;
+        let end = parse_date_time(field(Some(end_date)), field(Some(end_time)))
+            .ok_or_else(|| format!("invalid end on line {line}"))?;
+        let name = [field(description), field(task), field(project)]
+            .into_iter()
+            .find(|name| !name.is_empty())
+            .unwrap_or(UNTITLED);
+        entries.push(Entry {
+            name: name.to_string(),
+            project: import_project(&[field(client), field(project)]),
+            tags: field(tags).split(',').filter_map(tag_from_words).collect(),
+            start,
+            end: Some(end),
+            file: PathBuf::new(),
+            location: format!("on line {line}"),
+        });
+    }
+    Ok(entries)
+}
+
+fn from_unix(value: &Value) -> Option<SystemTime> {
+    let seconds = value.as_f64()?;
+    (seconds >= 0.0).then(|| SystemTime::UNIX_EPOCH + Duration::from_secs_f64(seconds))
+}
+
+/// Reads Watson's `frames` file, a list of `[start, stop, project, id, tags, updated_at]`.
+fn parse_watson(contents: &str) -> Result<Vec<Entry>, String> {
+    let frames: Vec<Vec<Value>> = serde_json::from_str(contents).map_err(|err| err.to_string())?;
+    frames
+        .iter()
+        .enumerate()
+        .map(|(index, frame)| {
+            let invalid = || format!("invalid frame at index {index}");
+            let start = frame.first().and_then(from_unix).ok_or_else(invalid)?;
+            let end = frame.get(1).and_then(from_unix).ok_or_else(invalid)?;
+            let project = frame.get(2).and_then(Value::as_str).ok_or_else(invalid)?;
+            let tags = frame.get(4).and_then(Value::as_array).map(Vec::as_slice).unwrap_or_default();
+            Ok(Entry {
+                name: project.to_string(),
+                project: import_project(&[project]),
+                tags: tags.iter().filter_map(Value::as_str).filter_map(tag_from_words).collect(),
+                start,
+                end: Some(end),
+                file: PathBuf::new(),
+                location: format!("at index {index}"),
+            })
+        })
+        .collect()
+}
+
+fn parse_timewarrior_time(value: &str) -> Option<SystemTime> {
+    let datetime = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%SZ").ok()?;
+    Some(Utc.from_utc_datetime(&datetime).into())
+}
+
+// Splits the tags after `#`, which are separated by spaces and quoted when they contain one.
+fn split_timewarrior_tags(tags: &str) -> Vec<String> {
+    let mut result = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    for c in tags.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !current.is_empty() {
+                    result.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        result.push(current);
+    }
+    result
+}
+
+/// Reads Timewarrior data files (`data/YYYY-MM.data`), one interval per line:
+/// `inc 20240304T090000Z - 20240304T103000Z # tag "other tag"`. Timewarrior only has
+/// tags, so they are used as the task name as well.
+fn parse_timewarrior(contents: &str) -> Result<Vec<Entry>, String> {
+    let mut entries = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let invalid = || format!("invalid interval on line {}", index + 1);
+        let (interval, tags) = line.split_once('#').unwrap_or((line, ""));
+        let mut words = interval.split_whitespace();
+        if words.next() != Some("inc") {
+            return Err(invalid());
+        }
+        let start = words.next().and_then(parse_timewarrior_time).ok_or_else(invalid)?;
+        let end = match words.next() {
+            Some("-") => Some(words.next().and_then(parse_timewarrior_time).ok_or_else(invalid)?),
+            Some(_) => return Err(invalid()),
+            None => None,
+        };
+        let tags = split_timewarrior_tags(tags);
+        let name = if tags.is_empty() { String::from(UNTITLED) } else { tags.join(", ") };
+        entries.push(Entry {
+            name,
+            project: None,
+            tags: tags.iter().filter_map(|tag| tag_from_words(tag)).collect(),
+            start,
+            end,
+            file: PathBuf::new(),
+            location: format!("on line {}", index + 1),
+        });
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local(y: i32, m: u32, d: u32, h: u32, min: u32) -> SystemTime {
+        Local.with_ymd_and_hms(y, m, d, h, min, 0).unwrap().into()
+    }
+
+    fn utc(y: i32, m: u32, d: u32, h: u32, min: u32) -> SystemTime {
+        Utc.with_ymd_and_hms(y, m, d, h, min, 0).unwrap().into()
+    }
+
+    #[test]
+    fn toggl_csv() {
+        let csv = "User,Email,Client,Project,Task,Description,Billable,Start date,Start time,End date,End time,Duration,Tags\n\
+                   Ana,ana@example.com,Acme,Backend,,\"Fix login, again\",Yes,2024-03-04,09:00:00,2024-03-04,10:30:00,01:30:00,\"bug, on call\"\n\
+                   Ana,ana@example.com,,,,,No,2024-03-04,23:00:00,2024-03-05,00:30:00,01:30:00,\n";
+        let entries = parse_csv(csv).unwrap();
+        assert_eq!(2, entries.len());
+        assert_eq!("Fix login, again", entries[0].name);
+        assert_eq!(Some(String::from("Acme/Backend")), entries[0].project);
+        assert_eq!(BTreeSet::from([String::from("bug"), String::from("on-call")]), entries[0].tags);
+        assert_eq!(local(2024, 3, 4, 9, 0), entries[0].start);
+        assert_eq!(Some(local(2024, 3, 4, 10, 30)), entries[0].end);
+        assert_eq!(UNTITLED, entries[1].name);
+        assert_eq!(None, entries[1].project);
+        assert_eq!(Some(local(2024, 3, 5, 0, 30)), entries[1].end);
+    }
+
+    #[test]
+    fn clockify_csv() {
+        let csv = "Project,Client,Description,Task,User,Group,Email,Tags,Billable,Start Date,Start Time,End Date,End Time,Duration (h),Duration (decimal)\n\
+                   Website,Acme,Design review,,Ana,,ana@example.com,,Yes,03/04/2024,02:15:00 PM,03/04/2024,03:00:00 PM,00:45:00,0.75\n";
+        let entries = parse_csv(csv).unwrap();
+        assert_eq!("Design review", entries[0].name);
+        assert_eq!(Some(String::from("Acme/Website")), entries[0].project);
+        assert_eq!(local(2024, 3, 4, 14, 15), entries[0].start);
+        assert_eq!(Some(local(2024, 3, 4, 15, 0)), entries[0].end);
+        assert!(parse_csv("Project,Description\nWebsite,Design\n").is_err());
+    }
+
+    #[test]
+    fn watson_frames() {
+        let json = r#"[[1709542800, 1709548200, "backend", "abc", ["bug", "urgent"], 1709548200]]"#;
+        let entries = parse_watson(json).unwrap();
+        assert_eq!("backend", entries[0].name);
+        assert_eq!(Some(String::from("backend")), entries[0].project);
+        assert_eq!(utc(2024, 3, 4, 9, 0), entries[0].start);
+        assert_eq!(Some(utc(2024, 3, 4, 10, 30)), entries[0].end);
+        assert_eq!(2, entries[0].tags.len());
+        assert!(parse_watson(r#"[["a"]]"#).is_err());
+    }
+
+    #[test]
+    fn timewarrior_data() {
+        let data = "inc 20240304T090000Z - 20240304T103000Z # backend \"code review\"\n\
+                    \n\
+                    inc 20240305T080000Z # backend \"code review\"\n";
+        let entries = parse_timewarrior(data).unwrap();
+        assert_eq!(2, entries.len());
+        assert_eq!("backend, code review", entries[0].name);
+        assert!(entries[0].tags.contains("code-review"));
+        assert_eq!(Some(utc(2024, 3, 4, 10, 30)), entries[0].end);
+        assert_eq!(None, entries[1].end);
+        assert!(parse_timewarrior("exc 20240304T090000Z").is_err());
+    }
+
+    fn entry(name: &str, start: SystemTime, end: Option<SystemTime>) -> Entry {
+        Entry {
+            name: name.to_string(),
+            project: None,
+            tags: BTreeSet::new(),
+            start,
+            end,
+            file: PathBuf::from("frames"),
+            location: format!("at index {name}"),
+        }
+    }
+
+    #[test]
+    fn entries_are_grouped_into_new_tasks() {
+        let mut tasks = HashMap::from([(1, Task::new(1, "existing"))]);
+        let entries = vec![
+            entry("a", utc(2024, 3, 5, 9, 0), Some(utc(2024, 3, 5, 10, 0))),
+            entry("b", utc(2024, 3, 4, 9, 0), None),
+            entry("a", utc(2024, 3, 4, 9, 0), Some(utc(2024, 3, 4, 9, 30))),
+        ];
+        assert_eq!(vec![2, 3], import_entries(&mut tasks, entries, false, utc(2024, 3, 6, 0, 0)).unwrap());
+        assert_eq!(2, tasks[&2].sessions.len());
+        assert_eq!(utc(2024, 3, 4, 9, 0), tasks[&2].sessions[0].start);
+        assert!(tasks[&3].running);
+    }
+
+    #[test]
+    fn invalid_entries_are_reported_where_they_are() {
+        let now = utc(2024, 3, 6, 0, 0);
+        let message = |entries: Vec<Entry>, exclusive_start: bool| {
+            let mut tasks = HashMap::from([(1, Task::new(1, "existing"))]);
+            let err = import_entries(&mut tasks, entries, exclusive_start, now).unwrap_err();
+            assert_eq!(1, tasks.len());
+            err.to_string()
+        };
+        let backwards = vec![entry("a", utc(2024, 3, 5, 9, 0), Some(utc(2024, 3, 5, 8, 0)))];
+        assert_eq!("Could not import frames: entry ends before it starts at index a", message(backwards, false));
+
+        let overlapping = vec![
+            entry("a", utc(2024, 3, 5, 9, 0), Some(utc(2024, 3, 5, 10, 0))),
+            entry("b", utc(2024, 3, 5, 9, 0), Some(utc(2024, 3, 5, 10, 0))),
+            entry("a", utc(2024, 3, 5, 9, 30), None),
+        ];
+        assert!(message(overlapping, false).ends_with("overlaps another entry of the same task at index a"));
+
+        let open = || vec![entry("a", utc(2024, 3, 5, 9, 0), None), entry("b", utc(2024, 3, 5, 10, 0), None)];
+        assert!(message(open(), true).ends_with("exclusive_start allows no second open entry at index b"));
+        let mut tasks = HashMap::new();
+        assert_eq!(2, import_entries(&mut tasks, open(), false, now).unwrap().len());
+    }
+}
@@ -0,0 +1,246 @@
+use std::time::{Duration, SystemTime};
+
+use serde_json::{json, Value};
+
+use crate::clock::ClockMark;
+use crate::task::{Session, Task};
+use crate::utils::format_duration;
+
+// Suspends and clock changes shorter than this are ignored, reading the clocks one after
+// the other already differs by a little.
+const TOLERANCE: Duration = Duration::from_secs(60);
+
+/// Something that happened while a task was running and makes its span on the wall
+/// clock doubtful.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Interruption {
+    /// The machine was suspended for this many seconds.
+    Suspended(u64),
+    /// The wall clock was moved by this many seconds, backwards when negative.
+    ClockJump(i64),
+    /// The machine was restarted, how long it was off is unknown.
+    Restarted,
+    /// The task ran this many seconds, longer than the longest plausible session.
+    LongSession(u64),
+}
+
+impl Interruption {
+    pub fn describe(&self) -> String {
+        match self {
+            Interruption::Suspended(seconds) => format!("the machine was suspended for {}", format_duration(*seconds)),
+            Interruption::ClockJump(seconds) if *seconds < 0 => {
+                format!("the clock was moved back by {}", format_duration(seconds.unsigned_abs()))
+            }
+            Interruption::ClockJump(seconds) => format!("the clock was moved forward by {}", format_duration(*seconds as u64)),
+            Interruption::Restarted => String::from("the machine was restarted"),
+            Interruption::LongSession(seconds) => format!("it ran for {} without a break", format_duration(*seconds)),
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        match self {
+            Interruption::Suspended(seconds) => json!({ "kind": "suspended", "seconds": seconds }),
+            Interruption::ClockJump(seconds) => json!({ "kind": "clock_jump", "seconds": seconds }),
+            Interruption::Restarted => json!({ "kind": "restarted" }),
+            Interruption::LongSession(seconds) => json!({ "kind": "long_session", "seconds": seconds }),
+        }
+    }
+}
+
+/// How to end an interrupted session.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Resolution {
+    /// The whole span.
+    Keep,
+    /// Only the time the machine was awake, at most the longest plausible session.
+    Cap,
+    /// Up to the last time `timer` changed tasks while the task ran.
+    LastActivity,
+}
+
+impl Resolution {
+    pub fn parse(value: &str) -> Option<Resolution> {
+        match value {
+            "keep" => Some(Resolution::Keep),
+            "cap" => Some(Resolution::Cap),
+            "last-activity" => Some(Resolution::LastActivity),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Resolution::Keep => "keep",
+            Resolution::Cap => "cap",
+            Resolution::LastActivity => "last-activity",
+        }
+    }
+}
+
+/// The running session of a task that was interrupted, with the ways to end it.
+#[derive(Clone, Debug)]
+pub struct SessionReview {
+    pub task_id: u32,
+    pub interruptions: Vec<Interruption>,
+    /// The whole span. Measured on the boot clock when the wall clock was moved.
+    pub full: Session,
+    pub capped: Session,
+    /// `None` when `timer` was not used while the task ran.
+    pub last_activity: Option<Session>,
+}
+
+impl SessionReview {
+    pub fn session(&self, resolution: Resolution) -> Option<&Session> {
+        match resolution {
+            Resolution::Keep => Some(&self.full),
+            Resolution::Cap => Some(&self.capped),
+            Resolution::LastActivity => self.last_activity.as_ref(),
+        }
+    }
+}
+
+/// Checks the running session of `task` as if it stopped at `at`, with `mark` read at
+/// `now`. Returns `None` when the task is not running or nothing interrupted it.
+pub fn review_session(
+    task: &Task,
+    at: SystemTime,
+    now: SystemTime,
+    mark: Option<&ClockMark>,
+    max_session: Duration,
+    last_activity: Option<SystemTime>,
+) -> Option<SessionReview> {
+    let start = task.last_run.filter(|_| task.running)?;
+    // A corrected start cannot go back before the end of the previous session.
+    let earliest = task.sessions.iter().map(|session| session.end).max().unwrap_or(SystemTime::UNIX_EPOCH);
+    let wall = signed_seconds(start, at);
+    let mut interruptions = Vec::new();
+    let mut full = Session { start, end: at.max(start) };
+    let mut awake = None;
+    // The mark was read at `now`, the session ends at `at`.
+    let end_mark = mark.and_then(|mark| mark.before(now.duration_since(at).unwrap_or_default()));
+    match (&task.start_mark, &end_mark) {
+        (Some(start_mark), Some(end_mark)) if start_mark.boot_id == end_mark.boot_id => {
+            let elapsed = end_mark.since_boot.saturating_sub(start_mark.since_boot);
+            let awake_time = end_mark.awake.saturating_sub(start_mark.awake);
+            let suspended = elapsed.saturating_sub(awake_time);
+            if suspended > TOLERANCE {
+                interruptions.push(Interruption::Suspended(suspended.as_secs()));
+            }
+            let jump = wall - elapsed.as_secs() as i64;
+            if jump.unsigned_abs() > TOLERANCE.as_secs() {
+                interruptions.push(Interruption::ClockJump(jump));
+                // The start was read before the clock moved, count back from the end instead.
+                let start = at.checked_sub(elapsed).unwrap_or(earliest).max(earliest);
+                full = Session { start, end: at.max(start) };
+            }
+            awake = Some(awake_time.as_secs());
+        }
+        (Some(_), Some(_)) => interruptions.push(Interruption::Restarted),
+        // Without marks only a clock moved back before the start shows.
+        _ if wall < -(TOLERANCE.as_secs() as i64) => interruptions.push(Interruption::ClockJump(wall)),
+        _ => {}
+    }
+    let worked = awake.unwrap_or(full.duration());
+    if worked > max_session.as_secs() {
+        interruptions.push(Interruption::LongSession(worked));
+    }
+    if interruptions.is_empty() {
+        return None;
+    }
+    let capped_end = full.start + Duration::from_secs(worked.min(max_session.as_secs()));
+    let last_activity = last_activity
+        .filter(|time| *time > full.start && *time < full.end)
+        .map(|time| Session { start: full.start, end: time });
+    Some(SessionReview {
+        task_id: task.id,
+        interruptions,
+        capped: Session { start: full.start, end: capped_end.min(full.end) },
+        full,
+        last_activity,
+    })
+}
+
+fn signed_seconds(from: SystemTime, to: SystemTime) -> i64 {
+    match to.duration_since(from) {
+        Ok(duration) => duration.as_secs() as i64,
+        Err(err) => -(err.duration().as_secs() as i64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: u64 = 3600;
+
+    fn mark(since_boot: u64, awake: u64) -> ClockMark {
+        ClockMark {
+            boot_id: String::from("boot"),
+            since_boot: Duration::from_secs(since_boot),
+            awake: Duration::from_secs(awake),
+        }
+    }
+
+    fn running(start: SystemTime, start_mark: Option<ClockMark>) -> Task {
+        let mut task = Task::new(1, "my task");
+        task.running = true;
+        task.last_run = Some(start);
+        task.start_mark = start_mark;
+        task
+    }
+
+    fn at(hours: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000 + hours * HOUR)
+    }
+
+    #[test]
+    fn uninterrupted_sessions_need_no_review() {
+        let task = running(at(0), Some(mark(HOUR, HOUR)));
+        let max = Duration::from_secs(12 * HOUR);
+        assert!(review_session(&task, at(2), at(2), Some(&mark(3 * HOUR, 3 * HOUR)), max, None).is_none());
+        // No marks, nothing to compare but the length.
+        let task = running(at(0), None);
+        assert!(review_session(&task, at(2), at(2), None, max, None).is_none());
+    }
+
+    #[test]
+    fn suspend_is_detected_and_capped() {
+        let task = running(at(0), Some(mark(HOUR, HOUR)));
+        // Two hours of work, then the laptop slept for ten.
+        let end = mark(13 * HOUR, 3 * HOUR);
+        let review = review_session(&task, at(12), at(12), Some(&end), Duration::from_secs(12 * HOUR), Some(at(2)))
+            .unwrap();
+        assert_eq!(vec![Interruption::Suspended(10 * HOUR)], review.interruptions);
+        assert_eq!(12 * HOUR, review.full.duration());
+        assert_eq!(2 * HOUR, review.capped.duration());
+        assert_eq!(at(2), review.session(Resolution::LastActivity).unwrap().end);
+    }
+
+    #[test]
+    fn clock_moved_back_keeps_the_elapsed_time() {
+        let task = running(at(5), Some(mark(HOUR, HOUR)));
+        // The clock was three hours ahead when the task started, one hour really passed.
+        let end = mark(2 * HOUR, 2 * HOUR);
+        let review = review_session(&task, at(3), at(3), Some(&end), Duration::from_secs(12 * HOUR), None).unwrap();
+        assert_eq!(vec![Interruption::ClockJump(-3 * HOUR as i64)], review.interruptions);
+        assert_eq!(Session { start: at(2), end: at(3) }.duration(), review.full.duration());
+        assert_eq!(at(2), review.full.start);
+        assert!(review.session(Resolution::LastActivity).is_none());
+    }
+
+    #[test]
+    fn long_sessions_and_restarts_are_flagged() {
+        let max = Duration::from_secs(8 * HOUR);
+        let task = running(at(0), None);
+        let review = review_session(&task, at(10), at(10), None, max, None).unwrap();
+        assert_eq!(vec![Interruption::LongSession(10 * HOUR)], review.interruptions);
+        assert_eq!(8 * HOUR, review.capped.duration());
+
+        let task = running(at(0), Some(mark(HOUR, HOUR)));
+        let mut other_boot = mark(HOUR, HOUR);
+        other_boot.boot_id = String::from("other");
+        let review = review_session(&task, at(2), at(2), Some(&other_boot), max, None).unwrap();
+        assert_eq!(vec![Interruption::Restarted], review.interruptions);
+        assert_eq!(2 * HOUR, review.full.duration());
+    }
+}
@@ -0,0 +1,303 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::TimerError;
+use crate::persistence::write_json;
+use crate::storage::Storage;
+use crate::task::Task;
+
+// Older entries are dropped, undo only needs to reach back a few commands.
+const MAX_ENTRIES: usize = 100;
+// The journal is rewritten by every command and entries hold whole tasks, so older entries
+// are also dropped once it grows past this many bytes. The last entry is always kept.
+const MAX_BYTES: usize = 1024 * 1024;
+
+/// State of one task before and after a command, `None` when it did not exist.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TaskChange {
+    pub list: String,
+    pub id: u32,
+    pub before: Option<Task>,
+    pub after: Option<Task>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct JournalEntry {
+    pub command: String,
+    pub time: SystemTime,
+    pub changes: Vec<TaskChange>,
+}
+
+/// Changes made by mutating commands. Entries before `position` can be undone,
+/// the ones from `position` on were undone and can be redone.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Journal {
+    pub entries: Vec<JournalEntry>,
+    pub position: usize,
+}
+
+fn journal_path(data_dir: &Path) -> PathBuf {
+    data_dir.join("journal.json")
+}
+
+// Tasks do not implement PartialEq, their serialized form is compared instead.
+fn same_task(a: Option<&Task>, b: Option<&Task>) -> bool {
+    serde_json::to_value(a).ok() == serde_json::to_value(b).ok()
+}
+
+/// Per-task differences between two versions of a task list.
+pub fn diff(list: &str, before: &HashMap<u32, Task>, after: &HashMap<u32, Task>) -> Vec<TaskChange> {
+    let ids: Vec<u32> = before.keys().chain(after.keys()).copied().collect();
+    diff_tasks(list, before, after, &ids)
+}
+
+/// Differences between two versions of a task list, looking only at the tasks `ids`.
+pub fn diff_tasks(list: &str, before: &HashMap<u32, Task>, after: &HashMap<u32, Task>, ids: &[u32]) -> Vec<TaskChange> {
+    let ids: BTreeSet<&u32> = ids.iter().collect();
+    ids.into_iter()
+        .filter(|id| !same_task(before.get(id), after.get(id)))
+        .map(|id| TaskChange {
+            list: list.to_string(),
+            id: *id,
+            before: before.get(id).cloned(),
+            after: after.get(id).cloned(),
+        })
+        .collect()
+}
+
+impl Journal {
+    /// Reads the journal, failing when it cannot be read.
+    pub fn read(data_dir: &Path) -> Result<Journal, TimerError> {
+        let path = journal_path(data_dir);
+        if !path.exists() {
+            return Ok(Journal::default());
+        }
+        let contents = fs::read_to_string(&path).map_err(TimerError::io(&path))?;
+        serde_json::from_str(&contents).map_err(|err| TimerError::CorruptFile(path, err))
+    }
+
+    /// Reads the journal. One that cannot be read is moved aside with a warning and a new
+    /// history is started, so a broken journal does not stop tasks from being changed.
+    pub fn load(data_dir: &Path) -> Result<Journal, TimerError> {
+        let err = match Journal::read(data_dir) {
+            Ok(journal) => return Ok(journal),
+            Err(err) => err,
+        };
+        let Ok(aside) = move_aside(data_dir) else {
+            return Err(err);
+        };
+        eprintln!("Warning: {err}, moved it to {} and started a new history", aside.display());
+        Ok(Journal::default())
+    }
+
+    pub fn save(&self, data_dir: &Path) -> Result<(), TimerError> {
+        write_json(data_dir, &journal_path(data_dir), self)
+    }
+
+    /// Adds an entry after the ones that can be undone, dropping those that were undone.
+    pub fn record(&mut self, entry: JournalEntry) {
+        self.entries.truncate(self.position);
+        self.entries.push(entry);
+        let mut excess = self.entries.len().saturating_sub(MAX_ENTRIES);
+        let sizes: Vec<usize> = self.entries[excess..]
+            .iter()
+            .map(|entry| serde_json::to_vec(entry).map_or(0, |bytes| bytes.len()))
+            .collect();
+        let mut total: usize = sizes.iter().sum();
+        for size in &sizes[..sizes.len() - 1] {
+            if total <= MAX_BYTES {
+                break;
+            }
+            total -= size;
+            excess += 1;
+        }
+        self.entries.drain(..excess);
+        self.position = self.entries.len();
+    }
+
+    /// Puts the tasks changed by the last command back as they were and returns that entry.
+    pub fn undo(&mut self, storage: &dyn Storage) -> Result<JournalEntry, TimerError> {
+        if self.position == 0 {
+            return Err(TimerError::EmptyHistory("undo"));
+        }
+        let entry = self.entries[self.position - 1].clone();
+        apply(storage, &entry.changes, true)?;
+        self.position -= 1;
+        Ok(entry)
+    }
+
+    /// Applies the last undone entry again and returns it.
+    pub fn redo(&mut self, storage: &dyn Storage) -> Result<JournalEntry, TimerError> {
+        let Some(entry) = self.entries.get(self.position).cloned() else {
+            return Err(TimerError::EmptyHistory("redo"));
+        };
+        apply(storage, &entry.changes, false)?;
+        self.position += 1;
+        Ok(entry)
+    }
+}
+
+/// Renames `journal.json` to `journal.json.corrupt` and returns the new path.
+pub fn move_aside(data_dir: &Path) -> Result<PathBuf, TimerError> {
+    let path = journal_path(data_dir);
+    let aside = path.with_extension("json.corrupt");
+    fs::rename(&path, &aside).map_err(TimerError::io(&path))?;
+    Ok(aside)
+}
+
+// Refuses to touch anything when a task no longer looks like the command left it,
+// for example after the file was edited by hand.
+fn apply(storage: &dyn Storage, changes: &[TaskChange], undo: bool) -> Result<(), TimerError> {
+    let lists: BTreeSet<&str> = changes.iter().map(|change| change.list.as_str()).collect();
+    let mut loaded = Vec::new();
+    for list in lists {
+        let mut tasks = storage.load_tasks(list)?;
+        let mut ids = Vec::new();
+        for change in changes.iter().filter(|change| change.list == list) {
+            let (expected, replacement) = if undo {
+                (&change.after, &change.before)
+            } else {
+                (&change.before, &change.after)
+            };
+            if !same_task(tasks.get(&change.id), expected.as_ref()) {
+                return Err(TimerError::HistoryConflict(change.id, list.to_string()));
+            }
+            match replacement {
+                Some(task) => tasks.insert(change.id, task.clone()),
+                None => tasks.remove(&change.id),
+            };
+            ids.push(change.id);
+        }
+        loaded.push((list, tasks, ids));
+    }
+    for (list, tasks, ids) in loaded {
+        storage.save_changed(list, &tasks, &ids, true)?;
+    }
+    Ok(())
+}
+
+/// Journals `changes` as made by `command` at `now`. Nothing is journaled without changes.
+pub fn record_changes(data_dir: &Path, command: &str, now: SystemTime, changes: Vec<TaskChange>) -> Result<(), TimerError> {
+    if changes.is_empty() {
+        return Ok(());
+    }
+    let mut journal = Journal::load(data_dir)?;
+    journal.record(JournalEntry {
+        command: command.to_string(),
+        time: now,
+        changes,
+    });
+    journal.save(data_dir)
+}
+
+/// Task lists as they were before a command replaced them, to journal what it changed.
+pub struct Snapshot {
+    lists: Vec<(String, HashMap<u32, Task>)>,
+}
+
+impl Snapshot {
+    pub fn take(storage: &dyn Storage, lists: &[&str]) -> Result<Snapshot, TimerError> {
+        let mut snapshot = Vec::new();
+        for list in lists {
+            snapshot.push((list.to_string(), storage.load_tasks(list)?));
+        }
+        Ok(Snapshot { lists: snapshot })
+    }
+
+    /// Compares the lists with what is saved now and journals the differences, if any, as
+    /// made at `now`.
+    pub fn record(self, storage: &dyn Storage, command: &str, now: SystemTime) -> Result<(), TimerError> {
+        let mut changes = Vec::new();
+        for (list, before) in &self.lists {
+            changes.extend(diff(list, before, &storage.load_tasks(list)?));
+        }
+        record_changes(storage.data_dir(), command, now, changes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(command: &str) -> JournalEntry {
+        JournalEntry {
+            command: command.to_string(),
+            time: SystemTime::now(),
+            changes: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn diff_lists_changed_tasks() {
+        let before = HashMap::from([(1, Task::new(1, "a")), (2, Task::new(2, "b"))]);
+        let mut after = before.clone();
+        after.remove(&1);
+        after.get_mut(&2).unwrap().rename("c");
+        after.insert(3, Task::new(3, "d"));
+        let changes = diff("current", &before, &after);
+        assert_eq!(3, changes.len());
+        assert!(changes[0].after.is_none());
+        assert_eq!("c", changes[1].after.as_ref().unwrap().name);
+        assert!(changes[2].before.is_none());
+        assert!(diff("current", &before, &before).is_empty());
+
+        // Only the given tasks are compared.
+        let changes = diff_tasks("current", &before, &after, &[2, 2, 4]);
+        assert_eq!(vec![2], changes.iter().map(|change| change.id).collect::<Vec<u32>>());
+    }
+
+    #[test]
+    fn record_drops_undone_entries() {
+        let mut journal = Journal::default();
+        journal.record(entry("create a"));
+        journal.record(entry("create b"));
+        journal.position = 1;
+        journal.record(entry("create c"));
+        let commands: Vec<&str> = journal.entries.iter().map(|entry| entry.command.as_str()).collect();
+        assert_eq!(vec!["create a", "create c"], commands);
+        assert_eq!(2, journal.position);
+
+        for index in 0..MAX_ENTRIES {
+            journal.record(entry(&format!("start {index}")));
+        }
+        assert_eq!(MAX_ENTRIES, journal.entries.len());
+        assert_eq!(MAX_ENTRIES, journal.position);
+    }
+
+    #[test]
+    fn large_entries_are_dropped_by_size() {
+        let mut journal = Journal::default();
+        let mut task = Task::new(1, "a");
+        task.rename(&"x".repeat(MAX_BYTES / 3));
+        for command in ["rename 1", "rename 2", "rename 3", "rename 4"] {
+            let mut large = entry(command);
+            large.changes.push(TaskChange { list: String::from("current"), id: 1, before: None, after: Some(task.clone()) });
+            journal.record(large);
+        }
+        let commands: Vec<&str> = journal.entries.iter().map(|entry| entry.command.as_str()).collect();
+        assert_eq!(vec!["rename 3", "rename 4"], commands);
+        assert_eq!(2, journal.position);
+
+        // Alone, an entry is kept whatever its size.
+        task.rename(&"x".repeat(2 * MAX_BYTES));
+        let mut huge = entry("rename 5");
+        huge.changes.push(TaskChange { list: String::from("current"), id: 1, before: None, after: Some(task) });
+        journal.record(huge);
+        assert_eq!(1, journal.entries.len());
+    }
+
+    #[test]
+    fn corrupt_journals_are_moved_aside() {
+        let data_dir = tempfile::tempdir().unwrap();
+        fs::write(journal_path(data_dir.path()), "{ not json").unwrap();
+        assert!(matches!(Journal::read(data_dir.path()), Err(TimerError::CorruptFile(..))));
+        assert!(Journal::load(data_dir.path()).unwrap().entries.is_empty());
+        assert!(!journal_path(data_dir.path()).exists());
+        let aside = fs::read_to_string(data_dir.path().join("journal.json.corrupt")).unwrap();
+        assert_eq!("{ not json", aside);
+    }
+}
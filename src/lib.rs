@@ -0,0 +1,28 @@
+//! Library behind the `timer` command: tasks with tracked sessions, kept in task lists
+//! in a data directory. [`TaskStore`] offers the same operations as the command.
+
+pub mod activity;
+pub mod backup;
+pub mod clock;
+pub mod config;
+pub mod doctor;
+pub mod duration;
+pub mod error;
+pub mod export;
+pub mod idle;
+pub mod import;
+pub mod interruption;
+pub mod journal;
+pub mod persistence;
+pub mod pomodoro;
+pub mod report;
+pub mod schema;
+pub mod storage;
+pub mod store;
+pub mod task;
+pub mod timestamp;
+pub mod utils;
+
+pub use error::TimerError;
+pub use store::{Started, TaskStore};
+pub use task::{Session, Task, TaskFilter};
@@ -1,26 +1,63 @@
-use core::panic;
-use std::{env, io};
 use std::collections::HashMap;
-use std::time::SystemTime;
+use std::env;
+use std::fs::File;
+use std::io::{self, IsTerminal, Write};
+use std::path::PathBuf;
+use std::process;
+use std::thread;
+use std::time::{Duration, SystemTime};
 
-use clap::{arg, ArgAction, ArgMatches, command, Command, value_parser};
+use clap::{arg, Arg, ArgAction, ArgMatches, command, Command, value_parser};
 
-use persistence::load_tasks;
-use persistence::save_tasks;
-use task::Task;
-use utils::format_duration;
+use chrono::{DateTime, Local, NaiveDate};
+use countdown::countdown;
+use output::{Output, OutputFormat};
+use serde_json::{json, Value};
+use simple_task_timer::clock::Clock;
+use simple_task_timer::config::Config;
+use simple_task_timer::error::TimerError;
+use simple_task_timer::export::{export_rows, write_csv};
+use simple_task_timer::idle::{IdleDecision, IdleReview};
+use simple_task_timer::import::{read_entries, ImportSource};
+use simple_task_timer::interruption::{Interruption, Resolution, SessionReview};
+use simple_task_timer::pomodoro::{notify, Phase, PomodoroSettings};
+use simple_task_timer::persistence::{lock_data_dir, migrate_legacy_files, resolve_data_dir};
+use simple_task_timer::report::{
+    build_report, print_project_totals, print_report, print_tag_totals, project_totals_to_json, report_to_json,
+    DateRange, Grouping,
+};
+use simple_task_timer::storage::{self, StorageKind};
+use simple_task_timer::store::{Started, TaskStore};
+use simple_task_timer::task::{
+    normalize_project, normalize_tag, parse_time, project_totals, tag_totals, Session, Task, TaskFilter,
+};
+use simple_task_timer::timestamp::{parse_interval, parse_timestamp};
+use simple_task_timer::utils::format_duration;
 
-mod persistence;
-mod task;
-mod utils;
+mod countdown;
+mod output;
+mod tui;
 
-fn list_tasks(tasks: &HashMap<u32, Task>, list_all: bool, show_timestamp: bool, show_base_timer: bool) {
+fn list_tasks(
+    out: &Output,
+    tasks: &HashMap<u32, Task>,
+    list_all: bool,
+    show_timestamp: bool,
+    show_base_timer: bool,
+    filter: &TaskFilter,
+    now: SystemTime,
+) {
     let mut ids: Vec<u32> = tasks
         .values()
         .filter(|x| x.running || list_all)
+        .filter(|x| filter.matches(x))
         .map(|x| x.id)
         .collect();
     ids.sort();
+    if out.is_json() {
+        out.print("", list_json(tasks, &ids, now));
+        return;
+    }
 
     if ids.is_empty() {
         println!(
@@ -30,204 +67,856 @@ fn list_tasks(tasks: &HashMap<u32, Task>, list_all: bool, show_timestamp: bool,
         return;
     }
 
+    let durations = || ids.iter().map(|id| (&tasks[id], tasks[id].current_duration(now)));
+    let totals_per_tag = tag_totals(durations());
+    let totals_per_project = project_totals(durations());
     let mut total_duration_tasks = 0;
     for id in ids {
-        let task = tasks.get(&id).unwrap();
-        let duration = task.current_duration();
+        let task = &tasks[&id];
+        let duration = task.current_duration(now);
         total_duration_tasks += duration;
-        println!("{}", task.to_print_string(show_timestamp, show_base_timer))
+        println!("{}", task.to_print_string(now, show_timestamp, show_base_timer))
     }
     let formatted_total_duration = format_duration(total_duration_tasks);
     println!("\nTotal: {formatted_total_duration}");
+    print_project_totals("Projects:", &totals_per_project);
+    print_tag_totals("Tags:", &totals_per_tag);
 }
 
-fn create_task(tasks: &mut HashMap<u32, Task>, task_name: &str, start: bool) {
-    let id = find_new_unique_id(&tasks);
-    let task = Task {
-        id,
-        name: String::from(task_name),
-        total_duration_seconds: 0,
-        running: start,
-        last_run: if start { Some(SystemTime::now()) } else { None },
-    };
-    tasks.insert(id, task);
-    println!("Task {} created with id {}", task_name, id);
+// The `list --output json` document for the tasks with the given ids, in that order.
+fn list_json(tasks: &HashMap<u32, Task>, ids: &[u32], now: SystemTime) -> Value {
+    let durations = || ids.iter().map(|id| (&tasks[id], tasks[id].current_duration(now)));
+    let total_duration_tasks: u64 = durations().map(|(_, duration)| duration).sum();
+    json!({
+        "tasks": ids.iter().map(|id| tasks[id].to_json(now)).collect::<Vec<Value>>(),
+        "total_seconds": total_duration_tasks,
+        "total": format_duration(total_duration_tasks),
+        "tag_totals_seconds": tag_totals(durations()),
+        "project_totals": project_totals_to_json(&project_totals(durations())),
+    })
+}
+
+fn task_action_json(action: &str, task: &Task, now: SystemTime) -> Value {
+    json!({ "action": action, "task": task.to_json(now) })
+}
+
+fn create_task(
+    out: &Output,
+    store: &TaskStore,
+    task_name: &str,
+    start: bool,
+    tags: Vec<String>,
+    project: Option<String>,
+    estimate: Option<u64>,
+) -> Result<(), TimerError> {
+    let created = store.create(task_name, tags, project, estimate, start)?;
+    let mut lines = stopped_lines(&created);
+    lines.push(format!("Task {} created with id {}", task_name, created.task.id));
+    out.print(&lines.join("\n"), created_json(&created, store.clock().now()));
+    Ok(())
+}
+
+fn created_json(created: &Started, now: SystemTime) -> Value {
+    let mut json = task_action_json("create", &created.task, now);
+    json["stopped"] = json!(created.stopped);
+    if !created.interrupted.is_empty() {
+        json["interrupted"] = interrupted_json(&created.interrupted);
+    }
+    json
+}
+
+fn delete_task_by_id(out: &Output, store: &TaskStore, task_id: u32) -> Result<(), TimerError> {
+    let task = store.delete(task_id)?;
+    out.print(&format!("Task {task_id} deleted"), task_action_json("delete", &task, store.clock().now()));
+    Ok(())
 }
 
-fn find_new_unique_id(tasks: &HashMap<u32, Task>) -> u32 {
-    tasks.keys()
-        .map(|k| *k)
-        .max()
-        .unwrap_or_default() + 1
+fn delete_task_by_name(out: &Output, store: &TaskStore, task_name: &str) -> Result<(), TimerError> {
+    let task = store.delete_by_name(task_name)?;
+    out.print(&format!("Task {task_name} deleted"), task_action_json("delete", &task, store.clock().now()));
+    Ok(())
 }
 
-fn delete_task_by_id(tasks: &mut HashMap<u32, Task>, task_id: &u32) {
-    if tasks.contains_key(task_id) {
-        tasks.remove(&task_id);
-        println!("Task {task_id} deleted");
+// Prints a start or switch, `action` names it in the JSON output.
+fn print_started(out: &Output, action: &str, started: Started, now: SystemTime) {
+    let mut lines = stopped_lines(&started);
+    if started.started {
+        lines.push(format!("Task {} started", started.task.id));
     } else {
-        task_does_not_exist(task_id);
+        lines.push(format!("Task {} is already running", started.task.id));
+    }
+    let mut json = task_action_json(action, &started.task, now);
+    if action == "switch" || !started.stopped.is_empty() {
+        json["stopped"] = json!(started.stopped);
+    }
+    if !started.interrupted.is_empty() {
+        json["interrupted"] = interrupted_json(&started.interrupted);
     }
+    out.print(&lines.join("\n"), json);
+}
+
+fn stopped_lines(started: &Started) -> Vec<String> {
+    let review = |id: &u32| started.interrupted.iter().find(|review| review.task_id == *id);
+    started
+        .stopped
+        .iter()
+        .map(|id| match review(id) {
+            Some(review) => format!("Task {id} stopped, {}", describe_capped(review)),
+            None => format!("Task {id} stopped"),
+        })
+        .collect()
+}
+
+// For sessions that were capped without asking, since they were stopped by another command.
+fn describe_capped(review: &SessionReview) -> String {
+    let reasons: Vec<String> = review.interruptions.iter().map(Interruption::describe).collect();
+    format!(
+        "recorded {} of {} because {}",
+        format_duration(review.capped.duration()),
+        format_duration(review.full.duration()),
+        reasons.join(", ")
+    )
 }
 
-fn delete_task_by_name(tasks: &mut HashMap<u32, Task>, task_name: &str) {
-    let filter: Vec<&Task> = tasks.values()
-        .filter(|t| t.name.eq(task_name))
+fn interrupted_json(reviews: &[SessionReview]) -> Value {
+    let reviews: Vec<Value> = reviews
+        .iter()
+        .map(|review| json!({
+            "id": review.task_id,
+            "interruptions": review.interruptions.iter().map(Interruption::to_json).collect::<Vec<Value>>(),
+            "recorded_seconds": review.capped.duration(),
+            "full_seconds": review.full.duration(),
+        }))
         .collect();
-    if filter.len() > 1 {
-        println!("Error: more than one task with name {task_name}.");
-    } else {
-        let task_opt = filter.last();
-        match task_opt {
+    json!(reviews)
+}
+
+fn stop_task(
+    out: &Output,
+    store: &TaskStore,
+    task_id: u32,
+    at: SystemTime,
+    resolution: Option<Resolution>,
+    idle_decision: Option<IdleDecision>,
+) -> Result<(), TimerError> {
+    let (task, mut lines, mut json) = stop_reviewed(out, store, task_id, at, resolution)?;
+    let (idle_lines, idle_json) = review_idle(out, store, Some(task.id), idle_decision)?;
+    if !idle_json.is_empty() {
+        lines.extend(idle_lines);
+        json["idle"] = json!(idle_json);
+        json["task"] = store.task(task_id)?.to_json(store.clock().now());
+    }
+    out.print(&lines.join("\n"), json);
+    Ok(())
+}
+
+// Stops the task as of `at`, asking how to end its session when it looks interrupted and
+// `resolution` does not say. Returns the task with the lines and JSON to print.
+fn stop_reviewed(
+    out: &Output,
+    store: &TaskStore,
+    task_id: u32,
+    at: SystemTime,
+    resolution: Option<Resolution>,
+) -> Result<(Task, Vec<String>, Value), TimerError> {
+    Ok(match store.review_stop(task_id, at)? {
+        None => {
+            let task = store.stop(task_id, at)?;
+            let json = task_action_json("stop", &task, store.clock().now());
+            (task, vec![format!("Task {task_id} stopped")], json)
+        }
+        Some(review) => {
+            let resolution = match resolution {
+                Some(resolution) => resolution,
+                None => ask_resolution(out, &review)?,
+            };
+            let Some(session) = review.session(resolution) else {
+                return Err(TimerError::InvalidArgument(format!(
+                    "timer changed no tasks while task {task_id} was running, there is no last activity to end it at"
+                )));
+            };
+            let task = store.end_session(task_id, session.clone())?;
+            let mut json = task_action_json("stop", &task, store.clock().now());
+            json["interruptions"] = review.interruptions.iter().map(Interruption::to_json).collect();
+            json["resolution"] = json!(resolution.name());
+            let line = format!("Task {task_id} stopped, recorded {}", format_duration(session.duration()));
+            (task, vec![line], json)
+        }
+    })
+}
+
+// Asks how to end an interrupted session. Without a terminal to ask on, the full span is
+// kept, like before interruptions were detected.
+fn ask_resolution(out: &Output, review: &SessionReview) -> Result<Resolution, TimerError> {
+    let reasons: Vec<String> = review.interruptions.iter().map(Interruption::describe).collect();
+    let warning = format!("Task {} looks interrupted: {}.", review.task_id, reasons.join(", "));
+    if !io::stdin().is_terminal() {
+        eprintln!("{warning} Keeping the full {}, pass --gap to choose.", format_duration(review.full.duration()));
+        return Ok(Resolution::Keep);
+    }
+    out.prompt(&warning);
+    out.prompt(&format!("  [k]eep the full {}", format_duration(review.full.duration())));
+    out.prompt(&format!("  [c]ap it at {}", format_duration(review.capped.duration())));
+    if let Some(session) = &review.last_activity {
+        let end = DateTime::<Local>::from(session.end).format("%Y-%m-%d %H:%M");
+        out.prompt(&format!("  end it at the [l]ast activity, {end}, {}", format_duration(session.duration())));
+    }
+    loop {
+        let mut input = String::new();
+        io::stdin().read_line(&mut input).map_err(TimerError::io("stdin"))?;
+        match input.trim().to_lowercase().as_str() {
+            "k" | "keep" => return Ok(Resolution::Keep),
+            "c" | "cap" => return Ok(Resolution::Cap),
+            "l" | "last-activity" if review.last_activity.is_some() => return Ok(Resolution::LastActivity),
+            _ => out.prompt("Invalid input. Please enter 'k', 'c' or 'l'."),
+        }
+    }
+}
+
+/// Goes through the idle periods of the list, or only those during which `task_id` ran,
+/// applying `decision` or asking for one. Without a terminal to ask on the periods are
+/// only listed. Returns the lines to print and the JSON of each period.
+fn review_idle(
+    out: &Output,
+    store: &TaskStore,
+    task_id: Option<u32>,
+    decision: Option<IdleDecision>,
+) -> Result<(Vec<String>, Vec<Value>), TimerError> {
+    let mut lines = Vec::new();
+    let mut periods = Vec::new();
+    for review in store.idle_reviews()? {
+        if task_id.is_some_and(|id| !review.tasks.iter().any(|(task, _)| *task == id)) {
+            continue;
+        }
+        let description = describe_idle(&review);
+        let mut json = json!({
+            "start": DateTime::<Local>::from(review.period.start).to_rfc3339(),
+            "end": DateTime::<Local>::from(review.period.end).to_rfc3339(),
+            "tasks": review.tasks.iter().map(|(id, seconds)| json!({ "id": id, "seconds": seconds })).collect::<Vec<Value>>(),
+        });
+        let decision = match decision {
+            Some(decision) => decision,
+            None if io::stdin().is_terminal() => ask_idle_decision(out, &description)?,
             None => {
-                task_does_not_exist_name(task_name);
-            }
-            Some(task) => {
-                tasks.remove(&task.id.clone());
-                println!("Task {task_name} deleted");
+                lines.push(format!("{description}, run timer idle review to handle it"));
+                periods.push(json);
+                continue;
             }
+        };
+        let seconds = store.resolve_idle(&review.period, decision)?;
+        let (name, outcome) = match decision {
+            IdleDecision::Discard => ("discard", format!("discarded {}", format_duration(seconds))),
+            IdleDecision::Keep => ("keep", String::from("kept")),
+            IdleDecision::Reassign(id) => ("reassign", format!("moved {} to task {id}", format_duration(seconds))),
+        };
+        json["decision"] = json!(name);
+        json["seconds"] = json!(seconds);
+        if let IdleDecision::Reassign(id) = decision {
+            json["reassigned_to"] = json!(id);
         }
+        lines.push(format!("{description}, {outcome}"));
+        periods.push(json);
     }
+    Ok((lines, periods))
+}
+
+fn describe_idle(review: &IdleReview) -> String {
+    let start = DateTime::<Local>::from(review.period.start);
+    let end = DateTime::<Local>::from(review.period.end);
+    let tasks: Vec<String> = review.tasks.iter().map(|(id, seconds)| format!("task {id} {}", format_duration(*seconds))).collect();
+    format!(
+        "Idle {} to {} ({}): {}",
+        start.format("%Y-%m-%d %H:%M"),
+        end.format("%H:%M"),
+        format_duration(review.period.duration()),
+        tasks.join(", ")
+    )
 }
 
-fn start_task(task: &mut Task) {
-    let res = task.start();
-    match res {
-        Ok(_) => {
-            println!("Task {} started", task.id);
+fn ask_idle_decision(out: &Output, description: &str) -> Result<IdleDecision, TimerError> {
+    out.prompt(description);
+    out.prompt("[d]iscard the idle time, [k]eep it or [r]eassign it to another task?");
+    loop {
+        let mut input = String::new();
+        io::stdin().read_line(&mut input).map_err(TimerError::io("stdin"))?;
+        match input.trim().to_lowercase().as_str() {
+            "d" | "discard" => return Ok(IdleDecision::Discard),
+            "k" | "keep" => return Ok(IdleDecision::Keep),
+            "r" | "reassign" => {
+                out.prompt("Task id to move the time to:");
+                let mut id = String::new();
+                io::stdin().read_line(&mut id).map_err(TimerError::io("stdin"))?;
+                match id.trim().parse::<u32>() {
+                    Ok(id) => return Ok(IdleDecision::Reassign(id)),
+                    Err(_) => out.prompt(&format!("'{}' is not a task id. [d]iscard, [k]eep or [r]eassign?", id.trim())),
+                }
+            }
+            _ => out.prompt("Invalid input. Please enter 'd', 'k' or 'r'."),
         }
-        Err(_) => {
-            println!("Task {} is already running", task.id);
+    }
+}
+
+fn idle_command(out: &Output, store: &TaskStore, matches: &ArgMatches) -> Result<(), TimerError> {
+    if let Some(watch_matches) = matches.subcommand_matches("watch") {
+        let interval = parse_time(get_string_arg(watch_matches, "interval"))?;
+        return watch_idle(out, store, Duration::from_secs(interval.max(1)));
+    }
+    let Some(review_matches) = matches.subcommand_matches("review") else {
+        return Ok(());
+    };
+    let decision = get_idle_decision_arg(review_matches, "discard", "keep", "reassign");
+    let (mut lines, periods) = review_idle(out, store, None, decision)?;
+    if lines.is_empty() {
+        lines.push(String::from("There are no idle periods to review."));
+    }
+    out.print(&lines.join("\n"), json!({ "action": "idle review", "periods": periods }));
+    Ok(())
+}
+
+/// Samples the idle time every `interval` until interrupted, recording the breaks taken
+/// while tasks run.
+fn watch_idle(out: &Output, store: &TaskStore, interval: Duration) -> Result<(), TimerError> {
+    out.prompt(&format!("Watching for idle time every {}, press Ctrl-C to stop.", format_duration(interval.as_secs())));
+    let mut reported = None;
+    loop {
+        if let Some(period) = store.watch_idle()? {
+            if reported != Some(period.start) {
+                reported = Some(period.start);
+                out.prompt(&format!("Idle since {}", DateTime::<Local>::from(period.start).format("%H:%M:%S")));
+            }
         }
+        thread::sleep(interval);
     }
 }
 
-fn stop_task(task: &mut Task) {
-    let res = task.stop();
-    match res {
-        Ok(_) => {
-            println!("Task {} stopped", task.id);
+fn log_session(out: &Output, store: &TaskStore, task_id: u32, session: Session) -> Result<(), TimerError> {
+    let duration = format_duration(session.duration());
+    let task = store.log(task_id, session)?;
+    out.print(
+        &format!("Logged {} to task {}, new timer: {}", duration, task.id, task.formatted_duration()),
+        task_action_json("log", &task, store.clock().now()),
+    );
+    Ok(())
+}
+
+fn run_pomodoros(
+    out: &Output,
+    store: &TaskStore,
+    task_id: u32,
+    settings: &PomodoroSettings,
+    cycles: Option<u32>,
+) -> Result<(), TimerError> {
+    // Without a terminal to read q from, nothing could stop the task if the run is killed.
+    if !io::stdin().is_terminal() {
+        return Err(TimerError::InvalidArgument(String::from(
+            "timer pomodoro needs a terminal to be stopped with q, use start and stop in scripts",
+        )));
+    }
+    // The countdown is drawn over the terminal, JSON output only gets the messages.
+    let show = !out.is_json() && io::stdout().is_terminal();
+    let mut task = store.task(task_id)?;
+    let mut completed = 0;
+    let mut phase = Phase::Work;
+    let finished = loop {
+        let length = settings.length(phase);
+        let label = match phase {
+            Phase::Work => {
+                match store.start(task_id, store.clock().now()) {
+                    Ok(started) => task = started.task,
+                    Err(TimerError::AlreadyRunning(_)) if completed == 0 => {
+                        out.prompt(&format!("Task {task_id} is already running, the first pomodoro ends its session"));
+                    }
+                    Err(err) => return Err(err),
+                }
+                format!("Pomodoro {} on [{}] '{}'", completed + 1, task.id, task.name)
+            }
+            _ => String::from(phase.describe()),
+        };
+        if !show {
+            out.prompt(&format!("{label} for {}", format_duration(length.as_secs())));
         }
-        Err(_) => {
-            println!("Task {} is not currently running", task.id);
+        if !countdown(&label, length, show)? {
+            break false;
         }
+        let next = match phase {
+            Phase::Work => {
+                let (finished, review) = store.finish_pomodoro(task_id)?;
+                if let Some(review) = review {
+                    out.prompt(&format!("Task {task_id} {}", describe_capped(&review)));
+                }
+                task = finished;
+                completed += 1;
+                let last = cycles.is_some_and(|cycles| completed >= cycles);
+                (!last).then(|| settings.break_after(completed))
+            }
+            _ => Some(Phase::Work),
+        };
+        let message = match next {
+            None => format!("Pomodoro {completed} done, that was the last one"),
+            Some(Phase::Work) => format!("Break over, back to '{}'", task.name),
+            Some(next) => format!("Pomodoro {completed} done, time for a {}", next.describe().to_lowercase()),
+        };
+        out.prompt(&message);
+        if let Some(command) = &settings.notify {
+            if let Err(err) = notify(command, next, &task, &message) {
+                eprintln!("Warning: {err}");
+            }
+        }
+        match next {
+            Some(next) => phase = next,
+            None => break true,
+        }
+    };
+    // Time worked on a pomodoro that was cut short is kept, without counting the pomodoro.
+    let mut lines = vec![format!(
+        "Completed {completed} pomodoro{} on task {task_id}, {} in total",
+        if completed == 1 { "" } else { "s" },
+        task.pomodoros.len()
+    )];
+    if !finished && phase == Phase::Work {
+        let (stopped, stop_lines, _) = stop_reviewed(out, store, task_id, store.clock().now(), None)?;
+        task = stopped;
+        lines.extend(stop_lines);
+        lines.push(format!("Task {task_id} stopped before the pomodoro ended, new timer: {}", task.formatted_duration()));
     }
+    let mut json = task_action_json("pomodoro", &task, store.clock().now());
+    json["completed"] = json!(completed);
+    json["interrupted"] = json!(!finished);
+    out.print(&lines.join("\n"), json);
+    Ok(())
 }
 
-fn rename_task(task: &mut Task, task_name: &str) {
-    task.rename(task_name);
-    println!("Task {} renamed to {}", task.id, task_name);
+fn rename_task(out: &Output, store: &TaskStore, task_id: u32, task_name: &str) -> Result<(), TimerError> {
+    let task = store.rename(task_id, task_name)?;
+    out.print(&format!("Task {} renamed to {}", task.id, task_name), task_action_json("rename", &task, store.clock().now()));
+    Ok(())
 }
 
-fn task_does_not_exist(task_id: &u32) {
-    println!("Task with id {task_id} does not exist");
+fn tag_task(out: &Output, store: &TaskStore, task_id: u32, tags: Vec<String>) -> Result<(), TimerError> {
+    let task = store.tag(task_id, tags)?;
+    out.print(&task.to_print_string(store.clock().now(), false, false), task_action_json("tag", &task, store.clock().now()));
+    Ok(())
 }
 
-fn task_does_not_exist_name(task_name: &str) {
-    println!("Task with name {task_name} does not exist");
+fn untag_task(out: &Output, store: &TaskStore, task_id: u32, tags: Vec<String>) -> Result<(), TimerError> {
+    let task = store.untag(task_id, &tags)?;
+    out.print(&task.to_print_string(store.clock().now(), false, false), task_action_json("untag", &task, store.clock().now()));
+    Ok(())
 }
 
-fn add_time(task: &mut Task, time: &str) {
-    task.add_time(time);
+fn set_project(out: &Output, store: &TaskStore, task_id: u32, project: Option<String>) -> Result<(), TimerError> {
+    let message = match &project {
+        Some(project) => format!("Task {task_id} moved to project {project}"),
+        None => format!("Task {task_id} removed from its project"),
+    };
+    let task = store.set_project(task_id, project)?;
+    out.print(&message, task_action_json("project", &task, store.clock().now()));
+    Ok(())
+}
+
+fn set_estimate(out: &Output, store: &TaskStore, task_id: u32, estimate: Option<u64>) -> Result<(), TimerError> {
+    let task = store.set_estimate(task_id, estimate)?;
+    let message = match estimate {
+        Some(estimate) => format!(
+            "Task {task_id} estimated at {}, tracked so far: {}",
+            format_duration(estimate),
+            format_duration(task.current_duration(store.clock().now()))
+        ),
+        None => format!("Task {task_id} no longer has an estimate"),
+    };
+    out.print(&message, task_action_json("estimate", &task, store.clock().now()));
+    Ok(())
+}
+
+fn add_time(out: &Output, store: &TaskStore, task_id: u32, time: &str) -> Result<(), TimerError> {
+    let task = store.add_time(task_id, time)?;
     let duration_formatted = task.formatted_duration();
-    println!("Added {time} to task with id {}, new timer: {duration_formatted}", task.id);
+    out.print(
+        &format!("Added {time} to task with id {}, new timer: {duration_formatted}", task.id),
+        task_action_json("add", &task, store.clock().now()),
+    );
+    Ok(())
 }
 
-fn subtract_time(task: &mut Task, time: &str) {
-    let res = task.subtract_time(time);
-    match res {
-        Ok(_) => {
-            let duration_formatted = task.formatted_duration();
-            println!("Subtracted {time} from task {}, new timer: {duration_formatted}", task.id);
-        }
-        Err(_) => {
-            println!("Task {} does not have enough time to subtract", task.id);
-        }
-    }
+fn subtract_time(out: &Output, store: &TaskStore, task_id: u32, time: &str) -> Result<(), TimerError> {
+    let task = store.subtract_time(task_id, time)?;
+    let duration_formatted = task.formatted_duration();
+    out.print(
+        &format!("Subtracted {time} from task {}, new timer: {duration_formatted}", task.id),
+        task_action_json("sub", &task, store.clock().now()),
+    );
+    Ok(())
 }
 
-fn set_time(task: &mut Task, time: &str) {
-    let res = task.set_time(time);
-    match res {
-        Ok(_) => {
-            println!("New time {time} set for task {}", task.id);
-        }
-        Err(_) => {
-            println!("Task {} is currently running, stop it before setting a new time.", task.id);
-        }
-    }
+fn set_time(out: &Output, store: &TaskStore, task_id: u32, time: &str) -> Result<(), TimerError> {
+    let task = store.set_time(task_id, time)?;
+    out.print(&format!("New time {time} set for task {}", task.id), task_action_json("set", &task, store.clock().now()));
+    Ok(())
 }
 
-fn archive_task(tasks: &mut HashMap<u32, Task>, task_id: &u32) {
-    if let Some(task) = tasks.get_mut(&task_id) {
-        if task.running {
-            println!("Task {task_id} is currently running, stop it before archiving.");
-            return;
-        }
-        let mut archived_tasks = load_tasks("archive");
-        let id = find_new_unique_id(&archived_tasks);
-        let arch_task = Task {
-            id,
-            name: task.name.clone(),
-            total_duration_seconds: task.total_duration_seconds.clone(),
-            running: task.running.clone(),
-            last_run: task.last_run.clone(),
-        };
-        archived_tasks.insert(id, arch_task);
-        tasks.remove(task_id);
-        save_tasks("archive", &archived_tasks);
-        println!("Task {task_id} archived with archive id {id}");
-    } else {
-        task_does_not_exist(task_id);
-    }
+fn archive_task(out: &Output, store: &TaskStore, task_id: u32) -> Result<(), TimerError> {
+    let arch_task = store.archive(task_id)?;
+    let mut json = task_action_json("archive", &arch_task, store.clock().now());
+    json["previous_id"] = json!(task_id);
+    out.print(&format!("Task {task_id} archived with archive id {}", arch_task.id), json);
+    Ok(())
+}
+
+fn unarchive_task(out: &Output, store: &TaskStore, task_id: u32) -> Result<(), TimerError> {
+    let task = store.unarchive(task_id)?;
+    let mut json = task_action_json("unarchive", &task, store.clock().now());
+    json["previous_id"] = json!(task_id);
+    out.print(&format!("Archived task {task_id} moved to {} with id {}", store.list(), task.id), json);
+    Ok(())
 }
 
-fn clear_tasks(tasks: &mut HashMap<u32, Task>, task_type: &str) {
-    let mut input = String::new();
+fn move_task(out: &Output, store: &TaskStore, task_id: u32, list: &str) -> Result<(), TimerError> {
+    let task = store.move_to(task_id, list)?;
+    let mut json = task_action_json("move", &task, store.clock().now());
+    json["previous_id"] = json!(task_id);
+    json["list"] = json!(list);
+    out.print(&format!("Task {task_id} moved to {list} with id {}", task.id), json);
+    Ok(())
+}
+
+fn clear_tasks(out: &Output, store: &TaskStore) -> Result<(), TimerError> {
     loop {
-        println!("Do you want to proceed clearing all {task_type} tasks? (Y/N)");
-        if let Err(_) = io::stdin().read_line(&mut input) {
-            println!("Error reading input.");
-            continue;
-        }
+        out.prompt(&format!("Do you want to proceed clearing all {} tasks? (Y/N)", store.list()));
+        let mut input = String::new();
+        io::stdin().read_line(&mut input).map_err(TimerError::io("stdin"))?;
         let response = input.trim().to_lowercase();
         if response.eq_ignore_ascii_case("y") {
             break;
         } else if response.eq_ignore_ascii_case("n") {
-            println!("Clearing canceled.");
-            return;
+            out.print("Clearing canceled.", json!({ "action": "clear", "canceled": true }));
+            return Ok(());
         } else {
-            println!("Invalid input. Please enter 'Y' or 'N'.");
+            out.prompt("Invalid input. Please enter 'Y' or 'N'.");
         }
     }
-    tasks.clear();
-    println!("Tasks cleared.");
+    let cleared = store.clear()?;
+    out.print("Tasks cleared.", json!({ "action": "clear", "canceled": false, "cleared": cleared }));
+    Ok(())
 }
 
-fn get_task<'a>(tasks: &'a mut HashMap<u32, Task>, task_id: &u32) -> Result<&'a mut Task, ()> {
-    return if let Some(task) = tasks.get_mut(&task_id) {
-        Ok(task)
+fn report_tasks(
+    out: &Output,
+    all_tasks: &HashMap<u32, Task>,
+    range: DateRange,
+    grouping: Grouping,
+    filter: &TaskFilter,
+    now: SystemTime,
+) {
+    let tasks: HashMap<u32, Task> = all_tasks
+        .iter()
+        .filter(|(_, task)| filter.matches(task))
+        .map(|(id, task)| (*id, task.clone()))
+        .collect();
+    let report = build_report(&tasks, range, grouping, now);
+    if out.is_json() {
+        out.print("", report_to_json(&report, &tasks));
     } else {
-        Err(())
+        print_report(&report, &tasks);
+    }
+}
+
+fn export_tasks(
+    lists: &[(&str, HashMap<u32, Task>)],
+    range: DateRange,
+    filter: &TaskFilter,
+    file: Option<&PathBuf>,
+    now: SystemTime,
+) -> Result<(), TimerError> {
+    let rows = export_rows(lists, range, filter, now);
+    match file {
+        Some(path) => {
+            let mut writer = io::BufWriter::new(File::create(path).map_err(TimerError::io(path))?);
+            write_csv(&mut writer, &rows)
+                .and_then(|_| writer.flush())
+                .map_err(TimerError::io(path))
+        }
+        None => write_csv(&mut io::stdout().lock(), &rows).map_err(TimerError::io("stdout")),
+    }
+}
+
+fn import_tasks(out: &Output, store: &TaskStore, source: ImportSource, files: &[PathBuf]) -> Result<(), TimerError> {
+    let mut entries = Vec::new();
+    for file in files {
+        entries.extend(read_entries(source, file)?);
+    }
+    let count = entries.len();
+    let tasks = store.import(entries)?;
+    let now = store.clock().now();
+    let imported: Vec<Value> = tasks.iter().map(|task| task.to_json(now)).collect();
+    let mut lines: Vec<String> = tasks.iter().map(|task| task.to_print_string(now, false, false)).collect();
+    lines.push(format!("Imported {count} entries into {} tasks", tasks.len()));
+    out.print(&lines.join("\n"), json!({ "action": "import", "entries": count, "tasks": imported }));
+    Ok(())
+}
+
+fn doctor(out: &Output, store: &TaskStore, fix: bool) -> Result<(), TimerError> {
+    let issues = store.doctor(fix)?;
+    let mut lines: Vec<String> = issues.iter().map(|issue| issue.to_print_string()).collect();
+    let repairable = issues.iter().filter(|issue| issue.repair.is_some()).count();
+    lines.push(if issues.is_empty() {
+        String::from("No problems found.")
+    } else if fix {
+        format!("Found {} problems, repaired {repairable}.", issues.len())
+    } else if repairable > 0 {
+        format!("Found {} problems, run timer doctor --fix to repair {repairable} of them.", issues.len())
+    } else {
+        format!("Found {} problems.", issues.len())
+    });
+    let issues_json: Vec<Value> = issues.iter().map(|issue| issue.to_json()).collect();
+    out.print(&lines.join("\n"), json!({ "action": "doctor", "fixed": fix, "issues": issues_json }));
+    Ok(())
+}
+
+fn backup_command(out: &Output, store: &TaskStore, matches: &ArgMatches) -> Result<(), TimerError> {
+    if let Some(restore_matches) = matches.subcommand_matches("restore") {
+        let (backup, tasks) = store.restore_backup(get_string_arg(restore_matches, "snapshot"))?;
+        let json = json!({ "action": "restore", "backup": backup.name, "list": backup.list, "tasks": tasks });
+        out.print(&format!("Restored {tasks} tasks of {} from {}", backup.list, backup.name), json);
+        return Ok(());
+    }
+    let mut lines = Vec::new();
+    let mut backups_json = Vec::new();
+    for backup in store.backups()?.iter().rev() {
+        let tasks = backup.load().map(|tasks| tasks.len()).ok();
+        let count = tasks.map_or(String::from("unreadable"), |count| format!("{count} tasks"));
+        let created = backup.created.with_timezone(&Local);
+        lines.push(format!("{}  {}  {count}", backup.name, created.format("%Y-%m-%d %H:%M:%S")));
+        backups_json.push(json!({
+            "name": backup.name,
+            "list": backup.list,
+            "created": created.format("%Y-%m-%dT%H:%M:%S%.6f").to_string(),
+            "tasks": tasks,
+        }));
+    }
+    if lines.is_empty() {
+        lines.push(String::from("There are no backups."));
+    }
+    out.print(&lines.join("\n"), json!({ "backups": backups_json }));
+    Ok(())
+}
+
+/// Undoes the last journaled command, or redoes the last undone one.
+fn step_history(out: &Output, store: &TaskStore, undo: bool) -> Result<(), TimerError> {
+    let entry = store.step_history(undo)?;
+    let now = store.clock().now();
+    let (action, verb) = if undo { ("undo", "Undid") } else { ("redo", "Redid") };
+    let mut lines = vec![format!("{verb} '{}'", entry.command)];
+    let mut changed = Vec::new();
+    for change in &entry.changes {
+        let task = if undo { &change.before } else { &change.after };
+        let line = match task {
+            Some(task) => format!("  {}: {}", change.list, task.to_print_string(now, false, false)),
+            None => format!("  {}: [{}] removed", change.list, change.id),
+        };
+        lines.push(line);
+        changed.push(json!({ "list": change.list, "id": change.id, "task": task.as_ref().map(|task| task.to_json(now)) }));
+    }
+    out.print(&lines.join("\n"), json!({ "action": action, "command": entry.command, "changes": changed }));
+    Ok(())
+}
+
+/// Copies every task list into another storage backend and switches the config file to it.
+/// The old files are left in place.
+fn migrate_storage(out: &Output, store: &TaskStore, from: StorageKind, to: StorageKind) -> Result<(), TimerError> {
+    if from == to {
+        return Err(TimerError::InvalidArgument(format!("The tasks are already stored in {}", to.name())));
+    }
+    let (lists, tasks) = store.copy_to_storage(to)?;
+    let data_dir = store.storage().data_dir();
+    let json = json!({ "action": "migrate-storage", "from": from.name(), "to": to.name(), "lists": lists, "tasks": tasks });
+    let message = format!(
+        "Copied {tasks} tasks in {lists} lists from {} to {} storage in {}",
+        from.name(),
+        to.name(),
+        data_dir.display()
+    );
+    out.print(&message, json);
+    Ok(())
+}
+
+fn get_range_arg(matches: &ArgMatches, clock: &dyn Clock) -> Result<DateRange, TimerError> {
+    let today = clock.local_now().date_naive();
+    if matches.get_flag("this-week") {
+        return Ok(DateRange::this_week(today));
+    }
+    if matches.get_flag("last-month") {
+        return Ok(DateRange::last_month(today));
+    }
+    Ok(DateRange {
+        from: get_date_arg(matches, "from")?,
+        to: get_date_arg(matches, "to")?,
+    })
+}
+
+fn get_date_arg(matches: &ArgMatches, name: &str) -> Result<Option<NaiveDate>, TimerError> {
+    matches.get_one::<String>(name)
+        .map(|date| {
+            NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| {
+                TimerError::InvalidArgument(format!("Could not parse the date '{date}', expected YYYY-MM-DD"))
+            })
+        })
+        .transpose()
+}
+
+// Tags given on the command line, `require_prefix` rejects values without a leading `+`
+// where tags follow other positional arguments.
+fn get_tags_arg(matches: &ArgMatches, name: &str, require_prefix: bool) -> Result<Vec<String>, TimerError> {
+    matches
+        .get_many::<String>(name)
+        .unwrap_or_default()
+        .map(|tag| {
+            if require_prefix && !tag.starts_with('+') {
+                return Err(TimerError::InvalidArgument(format!("Tags must start with +, got '{tag}'")));
+            }
+            normalize_tag(tag)
+        })
+        .collect()
+}
+
+fn get_project_arg(matches: &ArgMatches, name: &str) -> Result<Option<String>, TimerError> {
+    matches.get_one::<String>(name).map(|project| normalize_project(project)).transpose()
+}
+
+fn get_estimate_arg(matches: &ArgMatches) -> Result<Option<u64>, TimerError> {
+    let Some(estimate) = matches.get_one::<String>("estimate") else {
+        return Ok(None);
     };
+    match parse_time(estimate)? {
+        0 => Err(TimerError::InvalidArgument(format!("'{estimate}' is not a valid estimate, it must be longer than zero"))),
+        seconds => Ok(Some(seconds)),
+    }
 }
 
-fn get_task_id_arg(start_matches: &ArgMatches) -> u32 {
-    let task_id = start_matches
-        .get_one::<String>("task_id")
-        .expect("Id required")
-        .parse::<u32>()
-        .expect("Could not parse the id");
+fn get_filter_arg(matches: &ArgMatches) -> Result<TaskFilter, TimerError> {
+    Ok(TaskFilter {
+        tags: get_tags_arg(matches, "tag", false)?,
+        project: get_project_arg(matches, "project")?,
+    })
+}
+
+// Defaults to now when the argument is not given.
+fn get_timestamp_arg(matches: &ArgMatches, name: &str, clock: &dyn Clock) -> Result<SystemTime, TimerError> {
+    let Some(input) = matches.get_one::<String>(name) else {
+        return Ok(clock.now());
+    };
+    parse_timestamp(input, clock.local_now())
+        .map(SystemTime::from)
+        .ok_or_else(|| TimerError::InvalidTimestamp(input.clone()))
+}
+
+fn get_interval_arg(matches: &ArgMatches, name: &str, clock: &dyn Clock) -> Result<Session, TimerError> {
+    let input = get_string_arg(matches, name);
+    let (start, end) = parse_interval(input, clock.local_now()).ok_or_else(|| TimerError::InvalidTimestamp(input.to_string()))?;
+    Ok(Session { start: start.into(), end: end.into() })
+}
+
+// The flags deciding what to do with idle time, which only differ in name between commands.
+fn idle_decision_args(discard: &'static str, keep: &'static str, reassign: &'static str) -> [Arg; 3] {
+    [
+        Arg::new(discard)
+            .long(discard)
+            .help("Remove the time tasks ran while idle")
+            .action(ArgAction::SetTrue)
+            .conflicts_with_all([keep, reassign]),
+        Arg::new(keep)
+            .long(keep)
+            .help("Keep the time tasks ran while idle")
+            .action(ArgAction::SetTrue)
+            .conflicts_with(reassign),
+        Arg::new(reassign)
+            .long(reassign)
+            .value_name("TASK_ID")
+            .help("Move the time tasks ran while idle to another task")
+            .value_parser(value_parser!(u32)),
+    ]
+}
+
+fn get_idle_decision_arg(
+    matches: &ArgMatches,
+    discard: &str,
+    keep: &str,
+    reassign: &str,
+) -> Option<IdleDecision> {
+    if matches.get_flag(discard) {
+        return Some(IdleDecision::Discard);
+    }
+    if matches.get_flag(keep) {
+        return Some(IdleDecision::Keep);
+    }
+    matches.get_one::<u32>(reassign).map(|id| IdleDecision::Reassign(*id))
+}
+
+fn get_task_id_arg(matches: &ArgMatches) -> Result<u32, TimerError> {
+    let task_id = get_string_arg(matches, "task_id");
     task_id
+        .parse::<u32>()
+        .map_err(|_| TimerError::InvalidArgument(format!("Could not parse the id '{task_id}'")))
+}
+
+// The lengths given on the command line replace those of the config file.
+fn get_pomodoro_settings(matches: &ArgMatches, config: &Config) -> Result<PomodoroSettings, TimerError> {
+    let mut settings = PomodoroSettings::from_config(config)?;
+    let lengths = [
+        ("work", &mut settings.work),
+        ("short-break", &mut settings.short_break),
+        ("long-break", &mut settings.long_break),
+    ];
+    for (name, length) in lengths {
+        if let Some(value) = matches.get_one::<String>(name) {
+            *length = match parse_time(value)? {
+                0 => return Err(TimerError::InvalidArgument(format!("'{value}' is not a valid --{name}, it must be longer than zero"))),
+                seconds => Duration::from_secs(seconds),
+            };
+        }
+    }
+    if let Some(command) = matches.get_one::<String>("notify") {
+        settings.notify = Some(command.clone());
+    }
+    Ok(settings)
+}
+
+// Only for arguments marked as required, clap exits before running a command without them.
+fn get_string_arg<'a>(matches: &'a ArgMatches, name: &str) -> &'a str {
+    matches
+        .get_one::<String>(name)
+        .map(String::as_str)
+        .unwrap_or_default()
 }
 
 fn main() {
-    let matches = command!()
+    let matches = cli().get_matches();
+    let format = OutputFormat::parse(get_string_arg(&matches, "output")).unwrap_or(OutputFormat::Text);
+    let out = Output::new(format);
+    if let Err(err) = run(&out, &matches) {
+        out.error(&err);
+        process::exit(err.exit_code());
+    }
+}
+
+fn cli() -> Command {
+    command!()
         .arg(
-            arg!(-t --tasktype <VALUE> "Type of tasks you want to work on (current, archive)")
+            arg!(-t --tasktype <VALUE> "Task list to work on: current, archive or any other name")
+                .alias("list")
                 .value_parser(value_parser!(String))
                 .default_value("current"),
         )
+        .arg(
+            arg!(--"data-dir" <DIR> "Directory where tasks are stored, overrides TIMER_DATA_DIR and the config file")
+                .value_parser(value_parser!(PathBuf))
+                .required(false),
+        )
+        .arg(
+            arg!(-o --output <FORMAT> "Print results as text or as a JSON document")
+                .value_parser(["text", "json"])
+                .default_value("text")
+                .global(true),
+        )
         .subcommand(
             Command::new("list")
                 .about("List saved total time of current running tasks added to time elapsed from when it started running")
@@ -245,6 +934,86 @@ fn main() {
                     arg!(-b --base "Show the base timer, without adding running elapsed time")
                         .required(false)
                         .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    arg!(--tag <TAG> "Only list tasks with this tag, can be repeated")
+                        .required(false)
+                        .action(ArgAction::Append),
+                )
+                .arg(arg!(--project <PROJECT> "Only list tasks in this project or client").required(false)),
+        )
+        .subcommand(
+            Command::new("report")
+                .about("Report tracked time grouped by day, week or month")
+                .arg(
+                    arg!(--by <PERIOD> "Group by day, week or month")
+                        .value_parser(["day", "week", "month"])
+                        .default_value("day"),
+                )
+                .arg(arg!(--from <DATE> "First day of the report (YYYY-MM-DD)").required(false))
+                .arg(arg!(--to <DATE> "Last day of the report (YYYY-MM-DD)").required(false))
+                .arg(
+                    arg!(--"this-week" "Report the current week")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with_all(["from", "to", "last-month"]),
+                )
+                .arg(
+                    arg!(--"last-month" "Report the previous month")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with_all(["from", "to"]),
+                )
+                .arg(
+                    arg!(--tag <TAG> "Only report tasks with this tag, can be repeated")
+                        .required(false)
+                        .action(ArgAction::Append),
+                )
+                .arg(arg!(--project <PROJECT> "Only report tasks in this project or client").required(false)),
+        )
+        .subcommand(
+            Command::new("export")
+                .about("Export sessions as a CSV timesheet")
+                .arg(arg!(--format <FORMAT> "File format").value_parser(["csv"]).default_value("csv"))
+                .arg(
+                    arg!(-f --file <PATH> "Write to this file instead of the standard output")
+                        .required(false)
+                        .value_parser(value_parser!(PathBuf)),
+                )
+                .arg(
+                    arg!(-a --all "Export the tasks of every list together")
+                        .required(false)
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(arg!(--from <DATE> "Only sessions starting on or after this day (YYYY-MM-DD)").required(false))
+                .arg(arg!(--to <DATE> "Only sessions starting on or before this day (YYYY-MM-DD)").required(false))
+                .arg(
+                    arg!(--"this-week" "Export the current week")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with_all(["from", "to", "last-month"]),
+                )
+                .arg(
+                    arg!(--"last-month" "Export the previous month")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with_all(["from", "to"]),
+                )
+                .arg(
+                    arg!(--tag <TAG> "Only export tasks with this tag, can be repeated")
+                        .required(false)
+                        .action(ArgAction::Append),
+                )
+                .arg(arg!(--project <PROJECT> "Only export tasks in this project or client").required(false)),
+        )
+        .subcommand(
+            Command::new("import")
+                .about("Import time entries from Toggl Track, Clockify, Watson or Timewarrior")
+                .arg(
+                    arg!([source] "Tool the files come from")
+                        .required(true)
+                        .value_parser(["toggl", "clockify", "watson", "timewarrior"]),
+                )
+                .arg(
+                    arg!([files] ... "CSV export, Watson frames file or Timewarrior data files")
+                        .required(true)
+                        .value_parser(value_parser!(PathBuf)),
                 ),
         )
         .subcommand(
@@ -255,7 +1024,10 @@ fn main() {
                     arg!(-s --start "Start the timer after creating the task")
                         .required(false)
                         .action(ArgAction::SetTrue),
-                ),
+                )
+                .arg(arg!([tags] ... "Tags prefixed with +. Example: +backend +bug").required(false))
+                .arg(arg!(-p --project <PROJECT> "Client and project of the task. Example: acme/backend").required(false))
+                .arg(arg!(-e --estimate <DURATION> "How long the task is expected to take. Example: 6h").required(false)),
         )
         .subcommand(
             Command::new("delete")
@@ -270,12 +1042,42 @@ fn main() {
         .subcommand(
             Command::new("start")
                 .about("Start running a task timer")
-                .arg(arg!([task_id] "Task id").required(true)),
+                .arg(arg!([task_id] "Task id").required(true))
+                .arg(arg!(--at <TIME> "Start time. Examples: 09:15, yesterday 17:30, 20 minutes ago, 2024-03-04 09:15")),
+        )
+        .subcommand(
+            Command::new("switch")
+                .about("Stop all running tasks and start the given one")
+                .arg(arg!([task_id] "Task id").required(true))
+                .arg(arg!(--at <TIME> "Start time. Examples: 09:15, yesterday 17:30, 20 minutes ago, 2024-03-04 09:15")),
         )
         .subcommand(
             Command::new("stop")
                 .about("Stop running a task timer")
-                .arg(arg!([task_id] "Task id").required(true)),
+                .arg(arg!([task_id] "Task id").required(true))
+                .arg(arg!(--at <TIME> "Stop time. Examples: 09:15, yesterday 17:30, 20 minutes ago, 2024-03-04 09:15"))
+                .arg(
+                    arg!(--gap <CHOICE> "When the machine was suspended, restarted or its clock changed while the task ran: keep the full span, cap it at the time awake, or end it at the last activity")
+                        .required(false)
+                        .value_parser(["keep", "cap", "last-activity"]),
+                )
+                .args(idle_decision_args("discard-idle", "keep-idle", "reassign-idle")),
+        )
+        .subcommand(
+            Command::new("log")
+                .about("Record a session that was not tracked live")
+                .arg(arg!([task_id] "Task id").required(true))
+                .arg(arg!([interval] "Start and end. Examples: 09:00-10:30, yesterday 09:00-10:30").required(true)),
+        )
+        .subcommand(
+            Command::new("pomodoro")
+                .about("Work on a task in pomodoros, with short and long breaks in between")
+                .arg(arg!([task_id] "Task id").required(true))
+                .arg(arg!(--cycles <COUNT> "Stop after this many pomodoros").value_parser(value_parser!(u32).range(1..)))
+                .arg(arg!(--work <DURATION> "Length of a pomodoro, overrides pomodoro_work in the config file"))
+                .arg(arg!(--"short-break" <DURATION> "Length of a short break, overrides pomodoro_short_break"))
+                .arg(arg!(--"long-break" <DURATION> "Length of a long break, overrides pomodoro_long_break"))
+                .arg(arg!(--notify <COMMAND> "Shell command run when an interval ends, overrides pomodoro_notify")),
         )
         .subcommand(
             Command::new("rename")
@@ -283,131 +1085,257 @@ fn main() {
                 .arg(arg!([task_id] "Task id").required(true))
                 .arg(arg!([name] "Task name").required(true)),
         )
+        .subcommand(
+            Command::new("tag")
+                .about("Add tags to a task")
+                .arg(arg!([task_id] "Task id").required(true))
+                .arg(arg!([tags] ... "Tags, optionally prefixed with +").required(true)),
+        )
+        .subcommand(
+            Command::new("untag")
+                .about("Remove tags from a task")
+                .arg(arg!([task_id] "Task id").required(true))
+                .arg(arg!([tags] ... "Tags, optionally prefixed with +").required(true)),
+        )
+        .subcommand(
+            Command::new("project")
+                .about("Set the project of a task, or remove it when no project is given")
+                .arg(arg!([task_id] "Task id").required(true))
+                .arg(arg!([project] "Client and project. Example: acme/backend").required(false)),
+        )
+        .subcommand(
+            Command::new("estimate")
+                .about("Set how long a task is expected to take, or remove its estimate when none is given")
+                .arg(arg!([task_id] "Task id").required(true))
+                .arg(arg!([estimate] "Expected duration. Example: 8h").required(false)),
+        )
         .subcommand(
             Command::new("add")
                 .about("Add time to a task")
                 .arg(arg!([task_id] "Task id").required(true))
-                .arg(arg!([time] "Time in XXhYYm format. Example: 1h30m").required(true)),
+                .arg(arg!([time] "Duration. Examples: 1h30m, 90s, 1.5h, 2d, 1:30, PT1H30M").required(true)),
         )
         .subcommand(
             Command::new("sub")
                 .about("Subtract time from a task")
                 .arg(arg!([task_id] "Task id").required(true))
-                .arg(arg!([time] "Time in XXhYYm format. Example: 1h30m").required(true)),
+                .arg(arg!([time] "Duration. Examples: 1h30m, 90s, 1.5h, 2d, 1:30, PT1H30M").required(true)),
         )
         .subcommand(
             Command::new("set")
                 .about("Set the total duration time for a task")
                 .arg(arg!([task_id] "Task id").required(true))
-                .arg(arg!([time] "Time in XXhYYm format. Example: 1h30m").required(true)),
+                .arg(arg!([time] "Duration. Examples: 1h30m, 90s, 1.5h, 2d, 1:30, PT1H30M").required(true)),
         )
         .subcommand(
             Command::new("archive")
                 .about("Move a task to archive file")
                 .arg(arg!([task_id] "Task id").required(true)),
         )
+        .subcommand(
+            Command::new("unarchive")
+                .about("Move an archived task back to the selected task list")
+                .arg(arg!([task_id] "Archive id").required(true)),
+        )
+        .subcommand(
+            Command::new("move")
+                .about("Move a task to another task list")
+                .arg(arg!([task_id] "Task id").required(true))
+                .arg(arg!(--to <LIST> "Task list to move the task to").required(true)),
+        )
         .subcommand(Command::new("clear").about("Clear all tasks of the selected task type"))
-        .get_matches();
+        .subcommand(
+            Command::new("backup")
+                .about("List or restore the backups taken before task files change")
+                .subcommand_required(true)
+                .subcommand(Command::new("list").about("List backups, newest first"))
+                .subcommand(
+                    Command::new("restore")
+                        .about("Replace a task list with one of its backups")
+                        .arg(arg!([snapshot] "Backup name, as shown by backup list").required(true)),
+                ),
+        )
+        .subcommand(
+            Command::new("doctor")
+                .about("Check the task files for problems and optionally repair them")
+                .arg(arg!(--fix "Repair the problems that can be repaired").action(ArgAction::SetTrue)),
+        )
+        .subcommand(
+            Command::new("migrate-storage")
+                .about("Copy the tasks to another storage backend and switch to it")
+                .arg(arg!(<to> "Storage to migrate to").value_parser(["json", "sqlite"])),
+        )
+        .subcommand(
+            Command::new("idle")
+                .about("Record breaks taken while tasks run and decide what to do with them")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("watch")
+                        .about("Keep running and record the breaks taken while a task runs")
+                        .arg(arg!(--interval <DURATION> "Time between two checks").default_value("1m")),
+                )
+                .subcommand(
+                    Command::new("review")
+                        .about("Discard, keep or reassign the recorded breaks")
+                        .args(idle_decision_args("discard", "keep", "reassign")),
+                ),
+        )
+        .subcommand(Command::new("undo").about("Undo the last command that changed tasks"))
+        .subcommand(Command::new("redo").about("Redo the last undone command"))
+        .subcommand(Command::new("tui").about("Open an interactive view of the tasks with live timers"))
+}
 
-    let task_type = match matches.get_one::<String>("tasktype").unwrap().as_str() {
-        "current" => "current",
-        "archive" => "archive",
-        _ => panic!("Could not find a valid task type (current, archive)"),
-    };
+fn run(out: &Output, matches: &ArgMatches) -> Result<(), TimerError> {
+    let task_type = get_string_arg(matches, "tasktype");
 
-    let mut tasks = load_tasks(&task_type);
+    let config = Config::load()?;
+    let data_dir = resolve_data_dir(matches.get_one::<PathBuf>("data-dir"), &config)?;
+    {
+        let _lock = lock_data_dir(&data_dir)?;
+        migrate_legacy_files(&data_dir)?;
+    }
+    // Each change locks the data directory while it runs, so the interface can stay open.
+    let store = TaskStore::open(&data_dir, &config)?.with_list(task_type)?;
+    if matches.subcommand_matches("tui").is_some() {
+        return tui::run(&store);
+    }
+    let store = store.with_command(&command_line());
+    if let Some(doctor_matches) = matches.subcommand_matches("doctor") {
+        return doctor(out, &store, doctor_matches.get_flag("fix"));
+    }
+    if let Some(backup_matches) = matches.subcommand_matches("backup") {
+        return backup_command(out, &store, backup_matches);
+    }
+    if let Some(migrate_matches) = matches.subcommand_matches("migrate-storage") {
+        let to = StorageKind::parse(get_string_arg(migrate_matches, "to")).unwrap_or(StorageKind::Json);
+        let from = storage::data_dir_kind(&data_dir, config.storage)?;
+        return migrate_storage(out, &store, from, to);
+    }
+    if let Some(idle_matches) = matches.subcommand_matches("idle") {
+        return idle_command(out, &store, idle_matches);
+    }
+    if matches.subcommand_matches("undo").is_some() {
+        return step_history(out, &store, true);
+    }
+    if matches.subcommand_matches("redo").is_some() {
+        return step_history(out, &store, false);
+    }
 
     if let Some(list_matches) = matches.subcommand_matches("list") {
         let list_all = list_matches.get_flag("all");
         let show_timestamp = list_matches.get_flag("lasttime");
         let show_base_timer = list_matches.get_flag("base");
-        list_tasks(&tasks, list_all, show_timestamp, show_base_timer);
+        let filter = get_filter_arg(list_matches)?;
+        list_tasks(out, &store.tasks()?, list_all, show_timestamp, show_base_timer, &filter, store.clock().now());
+    } else if let Some(report_matches) = matches.subcommand_matches("report") {
+        let grouping = Grouping::parse(get_string_arg(report_matches, "by")).unwrap_or(Grouping::Day);
+        let range = get_range_arg(report_matches, store.clock())?;
+        let filter = get_filter_arg(report_matches)?;
+        report_tasks(out, &store.tasks_between(range)?, range, grouping, &filter, store.clock().now());
+    } else if let Some(export_matches) = matches.subcommand_matches("export") {
+        let range = get_range_arg(export_matches, store.clock())?;
+        let filter = get_filter_arg(export_matches)?;
+        let task_types = if export_matches.get_flag("all") { store.list_names()? } else { vec![task_type.to_string()] };
+        let mut lists = Vec::new();
+        for list in &task_types {
+            lists.push((list.as_str(), store.tasks_in(list)?));
+        }
+        let file = export_matches.get_one::<PathBuf>("file");
+        export_tasks(&lists, range, &filter, file, store.clock().now())?;
+    } else if let Some(import_matches) = matches.subcommand_matches("import") {
+        let source = ImportSource::parse(get_string_arg(import_matches, "source")).unwrap_or(ImportSource::Toggl);
+        let files: Vec<PathBuf> = import_matches.get_many::<PathBuf>("files").unwrap_or_default().cloned().collect();
+        import_tasks(out, &store, source, &files)?;
     } else if let Some(create_matches) = matches.subcommand_matches("create") {
         let start = create_matches.get_flag("start");
-        let task_name = create_matches
-            .get_one::<String>("name")
-            .expect("Name required");
-        create_task(&mut tasks, task_name, start);
+        let task_name = get_string_arg(create_matches, "name");
+        let tags = get_tags_arg(create_matches, "tags", true)?;
+        let project = get_project_arg(create_matches, "project")?;
+        let estimate = get_estimate_arg(create_matches)?;
+        create_task(out, &store, task_name, start, tags, project, estimate)?;
     } else if let Some(delete_matches) = matches.subcommand_matches("delete") {
-        let task_id = get_task_id_arg(delete_matches);
-        delete_task_by_id(&mut tasks, &task_id);
+        let task_id = get_task_id_arg(delete_matches)?;
+        delete_task_by_id(out, &store, task_id)?;
     } else if let Some(delete_name_matches) = matches.subcommand_matches("delname") {
-        let task_name = delete_name_matches
-            .get_one::<String>("name")
-            .expect("Name required");
-        delete_task_by_name(&mut tasks, &task_name);
+        let task_name = get_string_arg(delete_name_matches, "name");
+        delete_task_by_name(out, &store, task_name)?;
     } else if let Some(start_matches) = matches.subcommand_matches("start") {
-        let task_id = get_task_id_arg(start_matches);
-        let task_res = get_task(&mut tasks, &task_id);
-        if let Ok(task) = task_res {
-            start_task(task);
-        } else {
-            task_does_not_exist(&task_id);
-        }
+        let task_id = get_task_id_arg(start_matches)?;
+        let at = get_timestamp_arg(start_matches, "at", store.clock())?;
+        print_started(out, "start", store.start(task_id, at)?, store.clock().now());
+    } else if let Some(switch_matches) = matches.subcommand_matches("switch") {
+        let task_id = get_task_id_arg(switch_matches)?;
+        let at = get_timestamp_arg(switch_matches, "at", store.clock())?;
+        print_started(out, "switch", store.switch(task_id, at)?, store.clock().now());
     } else if let Some(stop_matches) = matches.subcommand_matches("stop") {
-        let task_id = get_task_id_arg(stop_matches);
-        let task_res = get_task(&mut tasks, &task_id);
-        if let Ok(task) = task_res {
-            stop_task(task);
-        } else {
-            task_does_not_exist(&task_id);
-        }
+        let task_id = get_task_id_arg(stop_matches)?;
+        let at = get_timestamp_arg(stop_matches, "at", store.clock())?;
+        let resolution = stop_matches.get_one::<String>("gap").and_then(|gap| Resolution::parse(gap));
+        let idle_decision = get_idle_decision_arg(stop_matches, "discard-idle", "keep-idle", "reassign-idle");
+        stop_task(out, &store, task_id, at, resolution, idle_decision)?;
+    } else if let Some(log_matches) = matches.subcommand_matches("log") {
+        let task_id = get_task_id_arg(log_matches)?;
+        let session = get_interval_arg(log_matches, "interval", store.clock())?;
+        log_session(out, &store, task_id, session)?;
+    } else if let Some(pomodoro_matches) = matches.subcommand_matches("pomodoro") {
+        let task_id = get_task_id_arg(pomodoro_matches)?;
+        let settings = get_pomodoro_settings(pomodoro_matches, &config)?;
+        let cycles = pomodoro_matches.get_one::<u32>("cycles").copied();
+        run_pomodoros(out, &store, task_id, &settings, cycles)?;
     } else if let Some(rename_matches) = matches.subcommand_matches("rename") {
-        let task_id = get_task_id_arg(rename_matches);
-        let task_name = rename_matches
-            .get_one::<String>("name")
-            .expect("Name required");
-        let task_res = get_task(&mut tasks, &task_id);
-        if let Ok(task) = task_res {
-            rename_task(task, task_name);
-        } else {
-            task_does_not_exist(&task_id);
-        }
+        let task_id = get_task_id_arg(rename_matches)?;
+        let task_name = get_string_arg(rename_matches, "name");
+        rename_task(out, &store, task_id, task_name)?;
+    } else if let Some(tag_matches) = matches.subcommand_matches("tag") {
+        let task_id = get_task_id_arg(tag_matches)?;
+        let tags = get_tags_arg(tag_matches, "tags", false)?;
+        tag_task(out, &store, task_id, tags)?;
+    } else if let Some(untag_matches) = matches.subcommand_matches("untag") {
+        let task_id = get_task_id_arg(untag_matches)?;
+        let tags = get_tags_arg(untag_matches, "tags", false)?;
+        untag_task(out, &store, task_id, tags)?;
+    } else if let Some(project_matches) = matches.subcommand_matches("project") {
+        let task_id = get_task_id_arg(project_matches)?;
+        let project = get_project_arg(project_matches, "project")?;
+        set_project(out, &store, task_id, project)?;
+    } else if let Some(estimate_matches) = matches.subcommand_matches("estimate") {
+        let task_id = get_task_id_arg(estimate_matches)?;
+        let estimate = get_estimate_arg(estimate_matches)?;
+        set_estimate(out, &store, task_id, estimate)?;
     } else if let Some(add_matches) = matches.subcommand_matches("add") {
-        let task_id = get_task_id_arg(add_matches);
-        let time: &String = add_matches
-            .get_one::<String>("time")
-            .expect("Time required");
-        let task_res = get_task(&mut tasks, &task_id);
-        if let Ok(task) = task_res {
-            add_time(task, time);
-        } else {
-            task_does_not_exist(&task_id);
-        }
+        let task_id = get_task_id_arg(add_matches)?;
+        let time = get_string_arg(add_matches, "time");
+        add_time(out, &store, task_id, time)?;
     } else if let Some(sub_matches) = matches.subcommand_matches("sub") {
-        let task_id = get_task_id_arg(sub_matches);
-        let time: &String = sub_matches
-            .get_one::<String>("time")
-            .expect("Time required");
-        let task_res = get_task(&mut tasks, &task_id);
-        if let Ok(task) = task_res {
-            subtract_time(task, time);
-        } else {
-            task_does_not_exist(&task_id);
-        }
+        let task_id = get_task_id_arg(sub_matches)?;
+        let time = get_string_arg(sub_matches, "time");
+        subtract_time(out, &store, task_id, time)?;
     } else if let Some(set_matches) = matches.subcommand_matches("set") {
-        let task_id = get_task_id_arg(set_matches);
-        let time: &String = set_matches
-            .get_one::<String>("time")
-            .expect("Time required");
-        let task_res = get_task(&mut tasks, &task_id);
-        if let Ok(task) = task_res {
-            set_time(task, time);
-        } else {
-            task_does_not_exist(&task_id);
-        }
+        let task_id = get_task_id_arg(set_matches)?;
+        let time = get_string_arg(set_matches, "time");
+        set_time(out, &store, task_id, time)?;
     } else if let Some(archive_matches) = matches.subcommand_matches("archive") {
-        if task_type == "archive" {
-            println!("Cannot archive archived tasks");
-            return;
-        }
-        let task_id = get_task_id_arg(archive_matches);
-        archive_task(&mut tasks, &task_id);
+        let task_id = get_task_id_arg(archive_matches)?;
+        archive_task(out, &store, task_id)?;
+    } else if let Some(unarchive_matches) = matches.subcommand_matches("unarchive") {
+        let task_id = get_task_id_arg(unarchive_matches)?;
+        unarchive_task(out, &store, task_id)?;
+    } else if let Some(move_matches) = matches.subcommand_matches("move") {
+        let task_id = get_task_id_arg(move_matches)?;
+        move_task(out, &store, task_id, get_string_arg(move_matches, "to"))?;
     } else if let Some(_clear_matches) = matches.subcommand_matches("clear") {
-        clear_tasks(&mut tasks, task_type);
+        clear_tasks(out, &store)?;
     }
+    Ok(())
+}
 
-    save_tasks(task_type, &tasks);
+// The arguments of this invocation, to describe journal entries.
+fn command_line() -> String {
+    let args: Vec<String> = env::args()
+        .skip(1)
+        .map(|arg| if arg.contains(char::is_whitespace) { format!("'{arg}'") } else { arg })
+        .collect();
+    args.join(" ")
 }
 
 #[cfg(test)]
@@ -417,35 +1345,52 @@ mod tests {
     #[test]
     fn list_all_tasks() {
         let mut tasks = HashMap::new();
-        tasks.insert(1, Task {
-            id: 1,
-            name: String::from("my task"),
-            total_duration_seconds: 0,
-            running: false,
-            last_run: None,
-        });
-        list_tasks(&tasks, true, false, false);
+        tasks.insert(1, Task::new(1, "my task"));
+        list_tasks(&Output::new(OutputFormat::Text), &tasks, true, false, false, &TaskFilter::default(), SystemTime::now());
     }
 
-    #[test]
-    fn unique_id() {
-        let mut tasks = HashMap::new();
-        tasks.insert(1, Task {
-            id: 1,
-            name: String::from("my task"),
-            total_duration_seconds: 0,
-            running: false,
-            last_run: None,
-        });
-        let id = find_new_unique_id(&tasks);
-        assert_eq!(id, 2);
+    fn keys(value: &Value) -> Vec<&str> {
+        let mut keys: Vec<&str> = value.as_object().unwrap().keys().map(String::as_str).collect();
+        keys.sort();
+        keys
     }
 
     #[test]
-    fn unique_id_empty_tasks() {
-        let tasks = HashMap::new();
-        let id = find_new_unique_id(&tasks);
-        assert_eq!(id, 1);
+    fn list_json_shape() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let mut task = Task::new(1, "my task");
+        task.sessions.push(Session { start: now - Duration::from_secs(90), end: now });
+        task.tags.insert(String::from("review"));
+        task.project = Some(String::from("acme/backend"));
+        let tasks = HashMap::from([(1, task), (2, Task::new(2, "other"))]);
+
+        let json = list_json(&tasks, &[1, 2], now);
+        assert_eq!(vec!["project_totals", "tag_totals_seconds", "tasks", "total", "total_seconds"], keys(&json));
+        assert_eq!(json!(90), json["total_seconds"]);
+        assert_eq!(json!("00:01:30"), json["total"]);
+        assert_eq!(json!({"review": 90}), json["tag_totals_seconds"]);
+        assert_eq!(json!({"project": "acme", "duration_seconds": 90}), json["project_totals"][1]);
+        let task = &json["tasks"][0];
+        assert_eq!(
+            vec![
+                "base_duration_seconds", "budget_used_percent", "duration", "duration_seconds", "estimate_seconds",
+                "id", "last_run", "name", "over_budget", "pomodoros", "project", "running", "sessions", "tags",
+            ],
+            keys(task),
+        );
+        assert_eq!((json!(1), json!("my task"), json!(90)), (task["id"].clone(), task["name"].clone(), task["duration_seconds"].clone()));
+        assert_eq!(vec!["duration_seconds", "end", "start"], keys(&task["sessions"][0]));
+        assert_eq!(json!(2), json["tasks"][1]["id"]);
     }
 
+    #[test]
+    fn create_json_shape() {
+        let now = SystemTime::now();
+        let created = Started { task: Task::new(3, "new"), stopped: vec![1, 2], started: true, interrupted: Vec::new() };
+        let json = created_json(&created, now);
+        assert_eq!(vec!["action", "stopped", "task"], keys(&json));
+        assert_eq!(json!("create"), json["action"]);
+        assert_eq!(json!([1, 2]), json["stopped"]);
+        assert_eq!(json!(3), json["task"]["id"]);
+    }
 }
@@ -0,0 +1,82 @@
+use serde_json::{json, Value};
+
+use simple_task_timer::error::TimerError;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
+impl OutputFormat {
+    pub fn parse(value: &str) -> Option<OutputFormat> {
+        match value {
+            "text" => Some(OutputFormat::Text),
+            "json" => Some(OutputFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+/// Prints command results either as the human readable messages or as one
+/// JSON document per invocation.
+pub struct Output {
+    pub format: OutputFormat,
+}
+
+impl Output {
+    pub fn new(format: OutputFormat) -> Output {
+        Output { format }
+    }
+
+    pub fn is_json(&self) -> bool {
+        self.format == OutputFormat::Json
+    }
+
+    pub fn print(&self, text: &str, json: Value) {
+        match self.format {
+            OutputFormat::Text => println!("{text}"),
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&json).unwrap_or_default()),
+        }
+    }
+
+    /// Questions asked to the user go to stderr in JSON mode to keep stdout parseable.
+    pub fn prompt(&self, text: &str) {
+        match self.format {
+            OutputFormat::Text => println!("{text}"),
+            OutputFormat::Json => eprintln!("{text}"),
+        }
+    }
+
+    pub fn error(&self, err: &TimerError) {
+        match self.format {
+            OutputFormat::Text => eprintln!("Error: {err}"),
+            OutputFormat::Json => self.print("", error_json(err)),
+        }
+    }
+}
+
+/// The document printed instead of a result when a command fails in JSON mode.
+pub fn error_json(err: &TimerError) -> Value {
+    json!({
+        "error": {
+            "kind": err.kind(),
+            "code": err.exit_code(),
+            "message": err.to_string(),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors_have_a_kind_code_and_message() {
+        let json = error_json(&TimerError::TaskNotFound(7));
+        assert_eq!(
+            json!({"error": {"kind": "task_not_found", "code": 3, "message": "Task with id 7 does not exist"}}),
+            json,
+        );
+    }
+}
@@ -1,37 +1,195 @@
-use std::collections::HashMap;
 use std::env;
-use std::fs::File;
-use std::io::{Read, Write};
-use std::path::PathBuf;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
 
-use crate::task::Task;
+use serde::Serialize;
 
-fn get_app_folder_path() -> PathBuf {
-    let mut app_path = env::current_exe().expect("Could not get the current executable path");
+use crate::config::{default_data_dir, Config};
+use crate::error::TimerError;
+
+const TASK_FILES: [&str; 2] = ["current.json", "archive.json"];
+// Other JSON files kept in the data directory, which cannot be used as task lists.
+const RESERVED_NAMES: [&str; 4] = ["journal", "activity", "idle", "storage"];
+
+/// Picks the data directory from, in order: the `--data-dir` flag, the
+/// `TIMER_DATA_DIR` environment variable, the config file and the XDG default.
+pub fn resolve_data_dir(flag: Option<&PathBuf>, config: &Config) -> Result<PathBuf, TimerError> {
+    match pick_data_dir(flag, env::var_os("TIMER_DATA_DIR"), config) {
+        Some(data_dir) => Ok(data_dir),
+        None => default_data_dir(),
+    }
+}
+
+// The first data directory that is set, before falling back to the XDG default.
+fn pick_data_dir(flag: Option<&PathBuf>, env_dir: Option<OsString>, config: &Config) -> Option<PathBuf> {
+    flag.cloned()
+        .or_else(|| env_dir.map(PathBuf::from))
+        .or_else(|| config.data_dir.clone())
+}
+
+// Older versions stored the tasks next to the executable.
+fn get_legacy_folder_path() -> Option<PathBuf> {
+    let mut app_path = env::current_exe().ok()?;
     app_path.pop(); // remove the executable name from the path
-    return app_path;
-}
-
-pub fn save_tasks(task_type: &str, tasks: &HashMap<u32, Task>) {
-    let app_folder_path = get_app_folder_path();
-    let mut json_file = String::from(task_type);
-    json_file.push_str(".json");
-    let current_tasks_path: PathBuf = app_folder_path.join(json_file);
-    let mut json_path = File::create(current_tasks_path).unwrap();
-    let task_string_json = serde_json::to_string_pretty(&tasks).unwrap();
-    json_path.write_all(task_string_json.as_bytes()).unwrap();
-}
-
-pub fn load_tasks(task_type: &str) -> HashMap<u32, Task> {
-    let app_folder_path = get_app_folder_path();
-    let mut json_file = String::from(task_type);
-    json_file.push_str(".json");
-    let current_tasks_path: PathBuf = app_folder_path.join(json_file);
-    if !current_tasks_path.exists() {
-        return HashMap::new();
-    }
-    let mut tasks_file = File::open(&current_tasks_path).unwrap();
-    let mut contents = String::new();
-    tasks_file.read_to_string(&mut contents).unwrap();
-    serde_json::from_str(&contents).unwrap()
+    Some(app_path)
+}
+
+/// Moves task files left next to the executable by older versions into `data_dir`,
+/// as long as `data_dir` does not have its own files yet.
+pub fn migrate_legacy_files(data_dir: &Path) -> Result<(), TimerError> {
+    match get_legacy_folder_path() {
+        Some(legacy_dir) => move_legacy_files(&legacy_dir, data_dir),
+        None => Ok(()),
+    }
+}
+
+fn move_legacy_files(legacy_dir: &Path, data_dir: &Path) -> Result<(), TimerError> {
+    if legacy_dir == data_dir || TASK_FILES.iter().any(|file| data_dir.join(file).exists()) {
+        return Ok(());
+    }
+    for file in TASK_FILES {
+        let legacy_path = legacy_dir.join(file);
+        if !legacy_path.exists() {
+            continue;
+        }
+        fs::create_dir_all(data_dir).map_err(TimerError::io(data_dir))?;
+        let new_path = data_dir.join(file);
+        // rename does not work across file systems, copy the file in that case
+        if fs::rename(&legacy_path, &new_path).is_err() {
+            fs::copy(&legacy_path, &new_path).map_err(TimerError::io(&new_path))?;
+            fs::remove_file(&legacy_path).map_err(TimerError::io(&legacy_path))?;
+        }
+        eprintln!("Moved {} to {}", legacy_path.display(), new_path.display());
+    }
+    Ok(())
+}
+
+/// Task list names become file names, so only letters, digits, `-` and `_` are accepted.
+pub fn validate_list_name(name: &str) -> Result<(), TimerError> {
+    let valid = name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_');
+    if RESERVED_NAMES.contains(&name) {
+        return Err(TimerError::InvalidArgument(format!("'{name}' is reserved and cannot be used as a task list name")));
+    }
+    if name.is_empty() || !valid {
+        return Err(TimerError::InvalidArgument(format!(
+            "'{name}' is not a valid task list name, use letters, digits, - and _"
+        )));
+    }
+    Ok(())
+}
+
+/// Takes an exclusive advisory lock on the data directory, waiting for other
+/// `timer` processes to release it. The lock is held until the file is dropped.
+pub fn lock_data_dir(data_dir: &Path) -> Result<File, TimerError> {
+    fs::create_dir_all(data_dir).map_err(TimerError::io(data_dir))?;
+    let lock_path = data_dir.join(".lock");
+    let lock_file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)
+        .map_err(TimerError::io(&lock_path))?;
+    lock_file.lock().map_err(TimerError::io(&lock_path))?;
+    Ok(lock_file)
+}
+
+/// Writes `value` to a temporary file and renames it over `path`, so an interrupted
+/// save never leaves a truncated file behind.
+pub fn write_json(data_dir: &Path, path: &Path, value: &impl Serialize) -> Result<(), TimerError> {
+    fs::create_dir_all(data_dir).map_err(TimerError::io(data_dir))?;
+    let temp_path = path.with_extension("json.tmp");
+    let contents = serde_json::to_string_pretty(value).map_err(|err| TimerError::CorruptFile(path.to_path_buf(), err))?;
+    let mut temp_file = File::create(&temp_path).map_err(TimerError::io(&temp_path))?;
+    temp_file.write_all(contents.as_bytes()).map_err(TimerError::io(&temp_path))?;
+    temp_file.sync_all().map_err(TimerError::io(&temp_path))?;
+    fs::rename(&temp_path, path).map_err(TimerError::io(path))?;
+    sync_dir(data_dir);
+    Ok(())
+}
+
+// Makes the rename itself durable. Directories cannot be opened as files on Windows.
+#[cfg(unix)]
+fn sync_dir(dir: &Path) {
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+    use std::fs::TryLockError;
+
+    #[test]
+    fn data_dir_flag_wins_over_env_and_config() {
+        let flag = PathBuf::from("/from/flag");
+        let env_dir = || Some(OsString::from("/from/env"));
+        let config = Config { data_dir: Some(PathBuf::from("/from/config")), ..Config::default() };
+        assert_eq!(Some(flag.clone()), pick_data_dir(Some(&flag), env_dir(), &config));
+        assert_eq!(Some(PathBuf::from("/from/env")), pick_data_dir(None, env_dir(), &config));
+        assert_eq!(Some(PathBuf::from("/from/config")), pick_data_dir(None, None, &config));
+        // Nothing set, so resolve_data_dir uses the XDG default.
+        assert_eq!(None, pick_data_dir(None, None, &Config::default()));
+    }
+
+    #[test]
+    fn legacy_files_move_into_an_empty_data_dir() {
+        let legacy_dir = tempfile::tempdir().unwrap();
+        let data_dir = tempfile::tempdir().unwrap();
+        let data_dir = data_dir.path().join("simple-task-timer");
+        fs::write(legacy_dir.path().join("current.json"), "{}").unwrap();
+        fs::write(legacy_dir.path().join("archive.json"), "[]").unwrap();
+        move_legacy_files(legacy_dir.path(), &data_dir).unwrap();
+        assert_eq!("{}", fs::read_to_string(data_dir.join("current.json")).unwrap());
+        assert_eq!("[]", fs::read_to_string(data_dir.join("archive.json")).unwrap());
+        assert!(!legacy_dir.path().join("current.json").exists());
+
+        // Files of their own in the data directory are never replaced.
+        fs::write(legacy_dir.path().join("current.json"), "old").unwrap();
+        move_legacy_files(legacy_dir.path(), &data_dir).unwrap();
+        assert_eq!("{}", fs::read_to_string(data_dir.join("current.json")).unwrap());
+        assert!(legacy_dir.path().join("current.json").exists());
+    }
+
+    #[test]
+    fn failed_writes_keep_the_old_file() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let path = data_dir.path().join("current.json");
+        write_json(data_dir.path(), &path, &vec![1, 2]).unwrap();
+
+        // JSON objects only have string keys, so this fails before anything is written.
+        let unserializable = HashMap::from([((1, 2), 3)]);
+        assert!(write_json(data_dir.path(), &path, &unserializable).is_err());
+        // The temporary file cannot be created where a directory is in the way.
+        fs::create_dir(path.with_extension("json.tmp")).unwrap();
+        assert!(write_json(data_dir.path(), &path, &vec![3]).is_err());
+        let saved: Vec<u32> = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(vec![1, 2], saved);
+    }
+
+    #[test]
+    fn data_dir_is_locked_until_dropped() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let lock = lock_data_dir(data_dir.path()).unwrap();
+        let other = File::open(data_dir.path().join(".lock")).unwrap();
+        assert!(matches!(other.try_lock(), Err(TryLockError::WouldBlock)));
+        drop(lock);
+        other.try_lock().unwrap();
+    }
+
+    #[test]
+    fn list_names_must_be_safe_file_names() {
+        assert!(validate_list_name("sprint-42").is_ok());
+        assert!(validate_list_name("work_2024").is_ok());
+        assert!(validate_list_name("../current").is_err());
+        assert!(validate_list_name("").is_err());
+        assert!(matches!(validate_list_name("journal"), Err(TimerError::InvalidArgument(_))));
+    }
 }
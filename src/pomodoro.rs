@@ -0,0 +1,148 @@
+use std::process::Command;
+use std::time::Duration;
+
+use crate::config::{duration_setting, Config};
+use crate::error::TimerError;
+use crate::task::Task;
+
+/// Lengths of the intervals of `timer pomodoro`, from the config file.
+#[derive(Clone, Debug)]
+pub struct PomodoroSettings {
+    pub work: Duration,
+    pub short_break: Duration,
+    pub long_break: Duration,
+    /// Every this many pomodoros the break is a long one.
+    pub long_break_every: u32,
+    /// Shell command run when an interval ends, for desktop notifications.
+    pub notify: Option<String>,
+}
+
+impl Default for PomodoroSettings {
+    fn default() -> PomodoroSettings {
+        PomodoroSettings {
+            work: Duration::from_secs(25 * 60),
+            short_break: Duration::from_secs(5 * 60),
+            long_break: Duration::from_secs(15 * 60),
+            long_break_every: 4,
+            notify: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    Work,
+    ShortBreak,
+    LongBreak,
+}
+
+impl Phase {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Phase::Work => "work",
+            Phase::ShortBreak => "short-break",
+            Phase::LongBreak => "long-break",
+        }
+    }
+
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Phase::Work => "Work",
+            Phase::ShortBreak => "Short break",
+            Phase::LongBreak => "Long break",
+        }
+    }
+}
+
+impl PomodoroSettings {
+    pub fn from_config(config: &Config) -> Result<PomodoroSettings, TimerError> {
+        if config.pomodoro_long_break_every == 0 {
+            return Err(TimerError::Config(String::from("pomodoro_long_break_every must be at least 1")));
+        }
+        Ok(PomodoroSettings {
+            work: length_setting("pomodoro_work", &config.pomodoro_work)?,
+            short_break: length_setting("pomodoro_short_break", &config.pomodoro_short_break)?,
+            long_break: length_setting("pomodoro_long_break", &config.pomodoro_long_break)?,
+            long_break_every: config.pomodoro_long_break_every,
+            notify: config.pomodoro_notify.clone(),
+        })
+    }
+
+    /// The break that follows the `completed`-th pomodoro of a run.
+    pub fn break_after(&self, completed: u32) -> Phase {
+        if completed > 0 && completed.is_multiple_of(self.long_break_every) {
+            Phase::LongBreak
+        } else {
+            Phase::ShortBreak
+        }
+    }
+
+    pub fn length(&self, phase: Phase) -> Duration {
+        match phase {
+            Phase::Work => self.work,
+            Phase::ShortBreak => self.short_break,
+            Phase::LongBreak => self.long_break,
+        }
+    }
+}
+
+// Reads an interval length. A zero length would run one pomodoro after another without
+// end, as fast as they can be saved.
+fn length_setting(name: &str, value: &str) -> Result<Duration, TimerError> {
+    let length = duration_setting(name, value)?;
+    if length.is_zero() {
+        return Err(TimerError::Config(format!("{name} '{value}' must be longer than zero")));
+    }
+    Ok(length)
+}
+
+/// Runs the notify command through the shell when `next` begins, `None` once the last
+/// pomodoro is done. The command reads what happened from `TIMER_PHASE` (`work`,
+/// `short-break`, `long-break` or `done`), `TIMER_TASK_ID`, `TIMER_TASK_NAME` and `TIMER_MESSAGE`.
+pub fn notify(command: &str, next: Option<Phase>, task: &Task, message: &str) -> Result<(), TimerError> {
+    let (shell, flag) = if cfg!(windows) { ("cmd", "/C") } else { ("sh", "-c") };
+    let status = Command::new(shell)
+        .args([flag, command])
+        .env("TIMER_PHASE", next.map_or("done", |phase| phase.name()))
+        .env("TIMER_TASK_ID", task.id.to_string())
+        .env("TIMER_TASK_NAME", &task.name)
+        .env("TIMER_MESSAGE", message)
+        .status()
+        .map_err(TimerError::io(shell))?;
+    if !status.success() {
+        return Err(TimerError::Config(format!("The pomodoro_notify command failed with {status}")));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_fourth_break_is_long() {
+        let settings = PomodoroSettings::default();
+        let breaks: Vec<Phase> = (1..=8).map(|completed| settings.break_after(completed)).collect();
+        assert_eq!(Phase::LongBreak, breaks[3]);
+        assert_eq!(Phase::LongBreak, breaks[7]);
+        assert_eq!(6, breaks.iter().filter(|phase| **phase == Phase::ShortBreak).count());
+        assert_eq!(Duration::from_secs(15 * 60), settings.length(breaks[3]));
+    }
+
+    #[test]
+    fn settings_come_from_the_config() {
+        let mut config = Config {
+            pomodoro_work: String::from("50m"),
+            pomodoro_long_break_every: 2,
+            ..Config::default()
+        };
+        let settings = PomodoroSettings::from_config(&config).unwrap();
+        assert_eq!(Duration::from_secs(50 * 60), settings.work);
+        assert_eq!(Phase::LongBreak, settings.break_after(2));
+        config.pomodoro_short_break = String::from("soon");
+        assert!(PomodoroSettings::from_config(&config).is_err());
+        config.pomodoro_short_break = String::from("5m");
+        config.pomodoro_work = String::from("0m");
+        assert!(matches!(PomodoroSettings::from_config(&config), Err(TimerError::Config(_))));
+    }
+}
@@ -0,0 +1,436 @@
+use std::collections::{BTreeMap, HashMap};
+use std::time::SystemTime;
+
+use chrono::{DateTime, Datelike, Days, Local, Months, NaiveDate, TimeZone};
+use serde_json::{json, Value};
+
+use crate::task::{project_totals, tag_totals, Session, Task};
+use crate::utils::{format_duration, format_signed_duration};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Grouping {
+    Day,
+    Week,
+    Month,
+}
+
+impl Grouping {
+    pub fn parse(value: &str) -> Option<Grouping> {
+        match value {
+            "day" => Some(Grouping::Day),
+            "week" => Some(Grouping::Week),
+            "month" => Some(Grouping::Month),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Grouping::Day => "day",
+            Grouping::Week => "week",
+            Grouping::Month => "month",
+        }
+    }
+
+    /// First day of the period containing `date`.
+    fn period_start(&self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Grouping::Day => date,
+            Grouping::Week => date - Days::new(date.weekday().num_days_from_monday() as u64),
+            Grouping::Month => date.with_day(1).unwrap(),
+        }
+    }
+
+    fn label(&self, period_start: NaiveDate) -> String {
+        match self {
+            Grouping::Day => period_start.format("%Y-%m-%d (%a)").to_string(),
+            Grouping::Week => {
+                let week = period_start.iso_week();
+                format!("{}-W{:02}", week.year(), week.week())
+            }
+            Grouping::Month => period_start.format("%Y-%m").to_string(),
+        }
+    }
+}
+
+/// Inclusive range of local dates, unbounded on the sides that are `None`.
+#[derive(Clone, Copy, Default)]
+pub struct DateRange {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+}
+
+impl DateRange {
+    pub fn this_week(today: NaiveDate) -> DateRange {
+        let monday = Grouping::Week.period_start(today);
+        DateRange {
+            from: Some(monday),
+            to: Some(monday + Days::new(6)),
+        }
+    }
+
+    pub fn last_month(today: NaiveDate) -> DateRange {
+        let first_of_this_month = Grouping::Month.period_start(today);
+        DateRange {
+            from: Some(first_of_this_month - Months::new(1)),
+            to: Some(first_of_this_month - Days::new(1)),
+        }
+    }
+
+    /// The range as times, from the first midnight to the one after the last day.
+    pub fn bounds(&self) -> (Option<SystemTime>, Option<SystemTime>) {
+        let from = self.from.map(|from| local_midnight(from).into());
+        let to = self.to.and_then(|to| to.succ_opt()).map(|after| local_midnight(after).into());
+        (from, to)
+    }
+
+    pub fn contains(&self, date: NaiveDate) -> bool {
+        self.from.is_none_or(|from| date >= from) && self.to.is_none_or(|to| date <= to)
+    }
+
+    pub fn describe(&self) -> String {
+        let format = |date: Option<NaiveDate>| date.map_or(String::from("..."), |d| d.to_string());
+        format!("{} - {}", format(self.from), format(self.to))
+    }
+}
+
+pub struct Report {
+    pub grouping: Grouping,
+    pub range: DateRange,
+    /// Seconds per task id, keyed by the first day of each period.
+    pub periods: BTreeMap<NaiveDate, BTreeMap<u32, u64>>,
+    /// Completed pomodoros per task id, keyed like `periods`.
+    pub pomodoros: BTreeMap<NaiveDate, BTreeMap<u32, u32>>,
+    /// Time added or subtracted manually, which has no date and cannot be placed in a period.
+    pub undated_seconds: i64,
+}
+
+impl Report {
+    pub fn task_totals(&self) -> BTreeMap<u32, u64> {
+        let mut totals = BTreeMap::new();
+        for durations in self.periods.values() {
+            for (id, seconds) in durations {
+                *totals.entry(*id).or_insert(0) += seconds;
+            }
+        }
+        totals
+    }
+
+    pub fn tag_totals(&self, tasks: &HashMap<u32, Task>) -> BTreeMap<String, u64> {
+        tag_totals(
+            self.task_totals()
+                .into_iter()
+                .filter_map(|(id, seconds)| tasks.get(&id).map(|task| (task, seconds))),
+        )
+    }
+
+    pub fn project_totals(&self, tasks: &HashMap<u32, Task>) -> BTreeMap<Vec<String>, u64> {
+        project_totals(
+            self.task_totals()
+                .into_iter()
+                .filter_map(|(id, seconds)| tasks.get(&id).map(|task| (task, seconds))),
+        )
+    }
+
+    pub fn pomodoro_totals(&self) -> BTreeMap<u32, u32> {
+        let mut totals = BTreeMap::new();
+        for counts in self.pomodoros.values() {
+            for (id, count) in counts {
+                *totals.entry(*id).or_insert(0) += count;
+            }
+        }
+        totals
+    }
+
+    pub fn total(&self) -> u64 {
+        self.periods.values().flat_map(|durations| durations.values()).sum()
+    }
+
+    pub fn pomodoro_total(&self) -> u32 {
+        self.pomodoros.values().flat_map(|counts| counts.values()).sum()
+    }
+}
+
+fn local_midnight(date: NaiveDate) -> DateTime<Local> {
+    let naive = date.and_hms_opt(0, 0, 0).unwrap();
+    // A DST change can skip midnight, in which case the day starts an hour later.
+    let one_hour = chrono::Duration::try_hours(1).unwrap();
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .or_else(|| Local.from_local_datetime(&(naive + one_hour)).earliest())
+        .unwrap()
+}
+
+/// Splits a session at local midnights and returns the seconds spent on each day.
+pub fn split_by_day(session: &Session) -> Vec<(NaiveDate, u64)> {
+    let mut days = Vec::new();
+    let mut start: DateTime<Local> = session.start.into();
+    let end: DateTime<Local> = session.end.into();
+    while start < end {
+        let date = start.date_naive();
+        let next_midnight = local_midnight(date.succ_opt().unwrap());
+        let day_end = next_midnight.min(end);
+        days.push((date, (day_end - start).num_seconds() as u64));
+        start = day_end;
+    }
+    days
+}
+
+pub fn build_report(tasks: &HashMap<u32, Task>, range: DateRange, grouping: Grouping, now: SystemTime) -> Report {
+    let mut periods: BTreeMap<NaiveDate, BTreeMap<u32, u64>> = BTreeMap::new();
+    let mut pomodoros: BTreeMap<NaiveDate, BTreeMap<u32, u32>> = BTreeMap::new();
+    let mut undated_seconds: i64 = 0;
+    for task in tasks.values() {
+        for session in task.sessions_until(now) {
+            for (date, seconds) in split_by_day(&session) {
+                if !range.contains(date) || seconds == 0 {
+                    continue;
+                }
+                let period = periods.entry(grouping.period_start(date)).or_default();
+                *period.entry(task.id).or_insert(0) += seconds;
+            }
+        }
+        for end in &task.pomodoros {
+            let date = DateTime::<Local>::from(*end).date_naive();
+            if range.contains(date) {
+                let period = pomodoros.entry(grouping.period_start(date)).or_default();
+                *period.entry(task.id).or_insert(0) += 1;
+            }
+        }
+        undated_seconds += task.adjustment_seconds;
+    }
+    Report {
+        grouping,
+        range,
+        periods,
+        pomodoros,
+        undated_seconds,
+    }
+}
+
+// ", 3 pomodoros" after a duration, nothing without any.
+fn pomodoro_suffix(count: Option<&u32>) -> String {
+    match count {
+        None | Some(0) => String::new(),
+        Some(1) => String::from(", 1 pomodoro"),
+        Some(count) => format!(", {count} pomodoros"),
+    }
+}
+
+pub fn print_report(report: &Report, tasks: &HashMap<u32, Task>) {
+    let task_name = |id: &u32| tasks.get(id).map_or(String::new(), |t| t.name.clone());
+    println!("Report {}", report.range.describe());
+
+    if report.periods.is_empty() {
+        println!("\nThere is no tracked time in this range.");
+    }
+    for (period_start, durations) in &report.periods {
+        println!("\n{}", report.grouping.label(*period_start));
+        let pomodoros = report.pomodoros.get(period_start);
+        for (id, seconds) in durations {
+            let count = pomodoros.and_then(|counts| counts.get(id));
+            println!("  [{}] '{}': {}{}", id, task_name(id), format_duration(*seconds), pomodoro_suffix(count));
+        }
+        let subtotal: u64 = durations.values().sum();
+        println!("  Subtotal: {}", format_duration(subtotal));
+    }
+
+    if report.periods.len() > 1 {
+        println!("\nPer task");
+        let pomodoros = report.pomodoro_totals();
+        for (id, seconds) in report.task_totals() {
+            println!("  [{}] '{}': {}{}", id, task_name(&id), format_duration(seconds), pomodoro_suffix(pomodoros.get(&id)));
+        }
+    }
+
+    print_project_totals("Per project", &report.project_totals(tasks));
+    print_tag_totals("Per tag", &report.tag_totals(tasks));
+    println!("\nTotal: {}{}", format_duration(report.total()), pomodoro_suffix(Some(&report.pomodoro_total())));
+    if report.undated_seconds != 0 {
+        println!(
+            "Not included: {} adjusted manually without a date",
+            format_signed_duration(report.undated_seconds)
+        );
+    }
+}
+
+pub fn print_tag_totals(header: &str, totals_per_tag: &BTreeMap<String, u64>) {
+    if totals_per_tag.is_empty() {
+        return;
+    }
+    println!("\n{header}");
+    for (tag, seconds) in totals_per_tag {
+        println!("  +{}: {}", tag, format_duration(*seconds));
+    }
+}
+
+/// Prints the project hierarchy indented by level. Nothing is printed when no task has a project.
+pub fn print_project_totals(header: &str, totals_per_project: &BTreeMap<Vec<String>, u64>) {
+    if totals_per_project.keys().all(|path| path.is_empty()) {
+        return;
+    }
+    println!("\n{header}");
+    for (path, seconds) in totals_per_project {
+        let label = path.last().map_or("(no project)", String::as_str);
+        let indent = "  ".repeat(path.len().max(1));
+        println!("{}{}: {}", indent, label, format_duration(*seconds));
+    }
+}
+
+pub fn project_totals_to_json(totals_per_project: &BTreeMap<Vec<String>, u64>) -> Value {
+    let entries: Vec<Value> = totals_per_project
+        .iter()
+        .map(|(path, seconds)| json!({
+            "project": if path.is_empty() { None } else { Some(path.join("/")) },
+            "duration_seconds": seconds,
+        }))
+        .collect();
+    json!(entries)
+}
+
+pub fn report_to_json(report: &Report, tasks: &HashMap<u32, Task>) -> Value {
+    let task_entry = |id: &u32, seconds: &u64, pomodoros: Option<&BTreeMap<u32, u32>>| json!({
+        "id": id,
+        "name": tasks.get(id).map(|t| t.name.as_str()),
+        "duration_seconds": seconds,
+        "duration": format_duration(*seconds),
+        "pomodoros": pomodoros.and_then(|counts| counts.get(id)).copied().unwrap_or(0),
+    });
+    let pomodoro_totals = report.pomodoro_totals();
+    let periods: Vec<Value> = report.periods
+        .iter()
+        .map(|(period_start, durations)| {
+            let subtotal: u64 = durations.values().sum();
+            let pomodoros = report.pomodoros.get(period_start);
+            json!({
+                "period": report.grouping.label(*period_start),
+                "start": period_start.to_string(),
+                "tasks": durations.iter().map(|(id, seconds)| task_entry(id, seconds, pomodoros)).collect::<Vec<Value>>(),
+                "subtotal_seconds": subtotal,
+            })
+        })
+        .collect();
+    json!({
+        "by": report.grouping.as_str(),
+        "from": report.range.from.map(|date| date.to_string()),
+        "to": report.range.to.map(|date| date.to_string()),
+        "periods": periods,
+        "tasks": report
+            .task_totals()
+            .iter()
+            .map(|(id, seconds)| task_entry(id, seconds, Some(&pomodoro_totals)))
+            .collect::<Vec<Value>>(),
+        "tag_totals_seconds": report.tag_totals(tasks),
+        "project_totals": project_totals_to_json(&report.project_totals(tasks)),
+        "total_seconds": report.total(),
+        "pomodoros": report.pomodoro_total(),
+        "undated_seconds": report.undated_seconds,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local(y: i32, m: u32, d: u32, h: u32, min: u32) -> SystemTime {
+        Local.with_ymd_and_hms(y, m, d, h, min, 0).unwrap().into()
+    }
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn task_with_sessions(id: u32, sessions: Vec<Session>) -> Task {
+        let mut task = Task::new(id, "my task");
+        task.sessions = sessions;
+        task
+    }
+
+    #[test]
+    fn session_split_at_midnight() {
+        let session = Session {
+            start: local(2024, 3, 4, 23, 30),
+            end: local(2024, 3, 5, 1, 0),
+        };
+        let days = split_by_day(&session);
+        assert_eq!(vec![(date(2024, 3, 4), 1800), (date(2024, 3, 5), 3600)], days);
+    }
+
+    #[test]
+    fn report_grouped_by_week() {
+        let mut tasks = HashMap::new();
+        tasks.insert(1, task_with_sessions(1, vec![
+            Session { start: local(2024, 3, 4, 9, 0), end: local(2024, 3, 4, 10, 0) },
+            Session { start: local(2024, 3, 10, 9, 0), end: local(2024, 3, 10, 9, 30) },
+            Session { start: local(2024, 3, 11, 9, 0), end: local(2024, 3, 11, 9, 15) },
+        ]));
+        let report = build_report(&tasks, DateRange::default(), Grouping::Week, SystemTime::now());
+        let weeks: Vec<(NaiveDate, u64)> = report.periods
+            .iter()
+            .map(|(start, durations)| (*start, durations[&1]))
+            .collect();
+        assert_eq!(vec![(date(2024, 3, 4), 5400), (date(2024, 3, 11), 900)], weeks);
+        assert_eq!("2024-W10", Grouping::Week.label(date(2024, 3, 4)));
+        assert_eq!(6300, report.total());
+    }
+
+    #[test]
+    fn pomodoros_are_counted_per_period() {
+        let mut task = task_with_sessions(1, vec![
+            Session { start: local(2024, 3, 4, 9, 0), end: local(2024, 3, 4, 9, 25) },
+            Session { start: local(2024, 3, 5, 9, 0), end: local(2024, 3, 5, 9, 25) },
+        ]);
+        task.pomodoros = vec![local(2024, 3, 4, 9, 25), local(2024, 3, 5, 9, 25)];
+        let tasks = HashMap::from([(1, task)]);
+        let range = DateRange { from: Some(date(2024, 3, 5)), to: None };
+        let report = build_report(&tasks, range, Grouping::Day, SystemTime::now());
+        assert_eq!(1, report.pomodoros[&date(2024, 3, 5)][&1]);
+        assert_eq!(1, report.pomodoro_total());
+        assert_eq!(", 1 pomodoro", pomodoro_suffix(report.pomodoro_totals().get(&1)));
+        assert_eq!(1, report_to_json(&report, &tasks)["tasks"][0]["pomodoros"]);
+    }
+
+    #[test]
+    fn report_respects_range_and_running_tasks() {
+        let mut task = task_with_sessions(1, vec![
+            Session { start: local(2024, 2, 28, 9, 0), end: local(2024, 2, 28, 10, 0) },
+        ]);
+        task.running = true;
+        task.last_run = Some(local(2024, 3, 1, 9, 0));
+        task.adjustment_seconds = 60;
+        let mut tasks = HashMap::new();
+        tasks.insert(1, task);
+        let range = DateRange { from: Some(date(2024, 3, 1)), to: None };
+        let report = build_report(&tasks, range, Grouping::Month, local(2024, 3, 1, 9, 45));
+        assert_eq!(2700, report.total());
+        assert_eq!(60, report.undated_seconds);
+    }
+
+    #[test]
+    fn subtracted_time_is_reported_as_negative() {
+        // `timer sub 1 30m` on a task with a one hour session.
+        let mut task = task_with_sessions(1, vec![
+            Session { start: local(2024, 3, 4, 9, 0), end: local(2024, 3, 4, 10, 0) },
+        ]);
+        task.adjustment_seconds = -1800;
+        let mut other = task_with_sessions(2, Vec::new());
+        other.adjustment_seconds = 600;
+        let tasks = HashMap::from([(1, task), (2, other)]);
+        let report = build_report(&tasks, DateRange::default(), Grouping::Day, local(2024, 3, 5, 0, 0));
+        assert_eq!(3600, report.total());
+        assert_eq!(-1200, report.undated_seconds);
+        assert_eq!(-1200, report_to_json(&report, &tasks)["undated_seconds"]);
+        assert_eq!("-00:20:00", format_signed_duration(report.undated_seconds));
+    }
+
+    #[test]
+    fn preset_ranges() {
+        let week = DateRange::this_week(date(2024, 3, 7));
+        assert_eq!(Some(date(2024, 3, 4)), week.from);
+        assert_eq!(Some(date(2024, 3, 10)), week.to);
+        let month = DateRange::last_month(date(2024, 3, 7));
+        assert_eq!(Some(date(2024, 2, 1)), month.from);
+        assert_eq!(Some(date(2024, 2, 29)), month.to);
+    }
+}
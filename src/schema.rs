@@ -0,0 +1,53 @@
+use serde_json::{json, Value};
+
+/// Version written in the envelope of task files: `{"version": 2, "tasks": {...}}`.
+pub const CURRENT_VERSION: u64 = 2;
+
+// MIGRATIONS[i] upgrades a file from version i + 1 to version i + 2.
+const MIGRATIONS: [fn(Value) -> Value; 1] = [wrap_in_envelope];
+
+/// Version 1 files are the bare map of tasks, written before the envelope existed.
+pub fn file_version(value: &Value) -> u64 {
+    match value.get("version") {
+        Some(version) if value.get("tasks").is_some() => version.as_u64().unwrap_or(0),
+        _ => 1,
+    }
+}
+
+/// Runs the migrations a file needs to reach the current version. Returns the version
+/// of the file as the error when it is newer than this program, or invalid.
+pub fn upgrade(mut value: Value) -> Result<Value, u64> {
+    let version = file_version(&value);
+    if version == 0 || version > CURRENT_VERSION {
+        return Err(version);
+    }
+    for migration in &MIGRATIONS[version as usize - 1..] {
+        value = migration(value);
+    }
+    Ok(value)
+}
+
+fn wrap_in_envelope(tasks: Value) -> Value {
+    json!({ "version": 2, "tasks": tasks })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bare_map_is_wrapped() {
+        let tasks = json!({ "1": { "id": 1, "name": "my task", "running": false, "last_run": null } });
+        assert_eq!(1, file_version(&tasks));
+        let upgraded = upgrade(tasks.clone()).unwrap();
+        assert_eq!(CURRENT_VERSION, file_version(&upgraded));
+        assert_eq!(tasks, upgraded["tasks"]);
+        assert_eq!(upgraded, upgrade(upgraded.clone()).unwrap());
+    }
+
+    #[test]
+    fn newer_versions_are_rejected() {
+        assert_eq!(Err(3), upgrade(json!({ "version": 3, "tasks": {} })));
+        assert_eq!(Err(0), upgrade(json!({ "version": "two", "tasks": {} })));
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::TimerError;
+use crate::persistence::write_json;
+use crate::task::Task;
+
+pub mod json;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+// Written by `migrate-storage`, so a data directory keeps its backend whichever config opens it.
+const KIND_FILE: &str = "storage.json";
+
+#[derive(Serialize, Deserialize)]
+struct KindFile {
+    storage: StorageKind,
+}
+
+/// Where the task lists of a data directory are kept. Everything else in the data
+/// directory, the journal and the backups, is stored the same way by every backend.
+/// Backends are `Send`, so a `TaskStore` can be moved to another thread.
+pub trait Storage: Send {
+    fn data_dir(&self) -> &Path;
+
+    /// Names the place a list is stored in, for messages.
+    fn location(&self, list: &str) -> String;
+
+    /// Names of the saved task lists, sorted with `current` and `archive` first.
+    fn list_names(&self) -> Result<Vec<String>, TimerError>;
+
+    /// Tasks of a list, empty when the list was never saved.
+    fn load_tasks(&self, list: &str) -> Result<HashMap<u32, Task>, TimerError>;
+
+    /// Saves the tasks of a list, first backing up the previous version when the tasks
+    /// changed and a backup is due, see `backup_due`. Saving unchanged tasks does nothing.
+    fn save_tasks(&self, list: &str, tasks: &HashMap<u32, Task>) -> Result<(), TimerError>;
+
+    /// Saves only the tasks `ids` of a list, the ones a command changed. Ids that are not
+    /// in `tasks` are deleted. Backends that keep a list in one file save all of it. With
+    /// `overwrite`, for changes that remove or overwrite task data, the previous version
+    /// is backed up even when a backup was taken within the backup interval.
+    fn save_changed(&self, list: &str, tasks: &HashMap<u32, Task>, ids: &[u32], overwrite: bool) -> Result<(), TimerError>;
+
+    /// Tasks of a list with only the sessions that overlap `from..to` and the pomodoros
+    /// that ended in it, for reports. Either side can be open.
+    fn load_tasks_between(
+        &self,
+        list: &str,
+        from: Option<SystemTime>,
+        to: Option<SystemTime>,
+    ) -> Result<HashMap<u32, Task>, TimerError> {
+        let mut tasks = self.load_tasks(list)?;
+        let after_from = |time: SystemTime| from.is_none_or(|from| time > from);
+        let before_to = |time: SystemTime| to.is_none_or(|to| time < to);
+        for task in tasks.values_mut() {
+            task.sessions.retain(|session| after_from(session.end) && before_to(session.start));
+            task.pomodoros.retain(|end| from.is_none_or(|from| *end >= from) && before_to(*end));
+        }
+        Ok(tasks)
+    }
+
+    /// Version of the older format a list is stored in, `None` when it is current.
+    /// Lists are upgraded the next time they are saved.
+    fn outdated_version(&self, list: &str) -> Result<Option<u64>, TimerError>;
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageKind {
+    Json,
+    Sqlite,
+}
+
+impl StorageKind {
+    pub fn parse(name: &str) -> Option<StorageKind> {
+        match name {
+            "json" => Some(StorageKind::Json),
+            "sqlite" => Some(StorageKind::Sqlite),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            StorageKind::Json => "json",
+            StorageKind::Sqlite => "sqlite",
+        }
+    }
+}
+
+/// The backend `data_dir` was migrated to, or `default` when it never was.
+pub fn data_dir_kind(data_dir: &Path, default: StorageKind) -> Result<StorageKind, TimerError> {
+    let path = data_dir.join(KIND_FILE);
+    if !path.exists() {
+        return Ok(default);
+    }
+    let contents = fs::read_to_string(&path).map_err(TimerError::io(&path))?;
+    let file: KindFile = serde_json::from_str(&contents).map_err(|err| TimerError::CorruptFile(path, err))?;
+    Ok(file.storage)
+}
+
+/// Makes `data_dir` open with `kind` from now on, regardless of the config.
+pub fn set_data_dir_kind(data_dir: &Path, kind: StorageKind) -> Result<(), TimerError> {
+    write_json(data_dir, &data_dir.join(KIND_FILE), &KindFile { storage: kind })
+}
+
+/// Opens the task lists of `data_dir` with the given backend, keeping `backup_count`
+/// backups of each list, taken at most every `backup_interval`.
+pub fn open(
+    data_dir: &Path,
+    kind: StorageKind,
+    backup_count: usize,
+    backup_interval: Duration,
+) -> Result<Box<dyn Storage>, TimerError> {
+    match kind {
+        StorageKind::Json => Ok(Box::new(json::JsonStorage::new(data_dir, backup_count).with_backup_interval(backup_interval))),
+        #[cfg(feature = "sqlite")]
+        StorageKind::Sqlite => {
+            let storage = sqlite::SqliteStorage::open(data_dir, backup_count)?.with_backup_interval(backup_interval);
+            Ok(Box::new(storage))
+        }
+        #[cfg(not(feature = "sqlite"))]
+        StorageKind::Sqlite => Err(TimerError::Config(String::from(
+            "This build of timer does not include SQLite storage, rebuild it with the sqlite feature",
+        ))),
+    }
+}
+
+/// Copies every task list from `from` to `to`, which must not contain any tasks yet.
+/// Returns the number of lists and tasks copied.
+pub fn copy_tasks(from: &dyn Storage, to: &dyn Storage) -> Result<(usize, usize), TimerError> {
+    for list in to.list_names()? {
+        if !to.load_tasks(&list)?.is_empty() {
+            return Err(TimerError::InvalidArgument(format!(
+                "{} already contains tasks, move it aside before migrating",
+                to.location(&list)
+            )));
+        }
+    }
+    let (mut lists, mut tasks) = (0, 0);
+    for list in from.list_names()? {
+        let list_tasks = from.load_tasks(&list)?;
+        if list_tasks.is_empty() {
+            continue;
+        }
+        to.save_tasks(&list, &list_tasks)?;
+        lists += 1;
+        tasks += list_tasks.len();
+    }
+    Ok((lists, tasks))
+}
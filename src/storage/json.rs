@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use crate::backup::{backup_due, create_backup};
+use crate::error::TimerError;
+use crate::persistence::{validate_list_name, write_json};
+use crate::schema::{file_version, upgrade, CURRENT_VERSION};
+use crate::storage::Storage;
+use crate::task::Task;
+
+/// One pretty-printed `<list>.json` file per task list, the original format.
+pub struct JsonStorage {
+    data_dir: PathBuf,
+    backup_count: usize,
+    backup_interval: Duration,
+}
+
+impl JsonStorage {
+    pub fn new(data_dir: &Path, backup_count: usize) -> JsonStorage {
+        JsonStorage {
+            data_dir: data_dir.to_path_buf(),
+            backup_count,
+            backup_interval: Duration::ZERO,
+        }
+    }
+
+    /// Backs up saves at most this often, see `backup_due`. Every save is backed up by default.
+    pub fn with_backup_interval(mut self, backup_interval: Duration) -> JsonStorage {
+        self.backup_interval = backup_interval;
+        self
+    }
+
+    // Saves the whole list, backing up the previous version when it is due or `overwrite` is set.
+    fn write_tasks(&self, task_type: &str, tasks: &HashMap<u32, Task>, overwrite: bool) -> Result<(), TimerError> {
+        let path = self.get_tasks_path(task_type);
+        let file = json!({ "version": CURRENT_VERSION, "tasks": tasks });
+        if path.exists() {
+            // Compared as JSON values, HashMap does not serialize its entries in a stable order.
+            let saved: Option<Value> = fs::read_to_string(&path)
+                .ok()
+                .and_then(|contents| serde_json::from_str(&contents).ok());
+            if saved.as_ref() == Some(&file) {
+                return Ok(());
+            }
+            if overwrite || backup_due(&self.data_dir, task_type, self.backup_interval)? {
+                create_backup(&self.data_dir, task_type, &path, self.backup_count)?;
+            }
+        }
+        write_json(&self.data_dir, &path, &file)
+    }
+
+    fn get_tasks_path(&self, task_type: &str) -> PathBuf {
+        let mut json_file = String::from(task_type);
+        json_file.push_str(".json");
+        self.data_dir.join(json_file)
+    }
+}
+
+impl Storage for JsonStorage {
+    fn data_dir(&self) -> &Path {
+        &self.data_dir
+    }
+
+    fn location(&self, list: &str) -> String {
+        format!("{list}.json")
+    }
+
+    fn list_names(&self) -> Result<Vec<String>, TimerError> {
+        let mut names = vec![String::from("current"), String::from("archive")];
+        let entries = match fs::read_dir(&self.data_dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(names),
+            Err(err) => return Err(TimerError::Io(self.data_dir.clone(), err)),
+        };
+        let mut others = Vec::new();
+        for entry in entries {
+            let path = entry.map_err(TimerError::io(&self.data_dir))?.path();
+            let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            let is_json = path.extension().is_some_and(|extension| extension == "json");
+            if is_json && validate_list_name(name).is_ok() && !names.iter().any(|known| known == name) {
+                others.push(name.to_string());
+            }
+        }
+        others.sort();
+        names.extend(others);
+        Ok(names)
+    }
+
+    fn load_tasks(&self, task_type: &str) -> Result<HashMap<u32, Task>, TimerError> {
+        let current_tasks_path = self.get_tasks_path(task_type);
+        if !current_tasks_path.exists() {
+            return Ok(HashMap::new());
+        }
+        read_tasks(&current_tasks_path)
+    }
+
+    fn save_tasks(&self, task_type: &str, tasks: &HashMap<u32, Task>) -> Result<(), TimerError> {
+        self.write_tasks(task_type, tasks, false)
+    }
+
+    // A list is one file, it is saved whole whatever changed.
+    fn save_changed(&self, list: &str, tasks: &HashMap<u32, Task>, _ids: &[u32], overwrite: bool) -> Result<(), TimerError> {
+        self.write_tasks(list, tasks, overwrite)
+    }
+
+    fn outdated_version(&self, list: &str) -> Result<Option<u64>, TimerError> {
+        let path = self.get_tasks_path(list);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let contents = fs::read_to_string(&path).map_err(TimerError::io(&path))?;
+        let value: Value = serde_json::from_str(&contents).map_err(|err| TimerError::CorruptFile(path, err))?;
+        let version = file_version(&value);
+        Ok(Some(version).filter(|version| *version < CURRENT_VERSION))
+    }
+}
+
+/// Reads a task file of any known version, upgrading older ones in memory. They are
+/// written in the current format the next time they are saved.
+pub fn read_tasks(path: &Path) -> Result<HashMap<u32, Task>, TimerError> {
+    let contents = fs::read_to_string(path).map_err(TimerError::io(path))?;
+    let corrupt = |err| TimerError::CorruptFile(path.to_path_buf(), err);
+    let value: Value = serde_json::from_str(&contents).map_err(corrupt)?;
+    let mut file = upgrade(value).map_err(|version| TimerError::UnsupportedVersion(path.to_path_buf(), version))?;
+    serde_json::from_value(file["tasks"].take()).map_err(corrupt)
+}
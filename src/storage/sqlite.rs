@@ -0,0 +1,426 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rusqlite::{params, Connection};
+
+use crate::backup::{backup_due, backup_tasks};
+use crate::error::TimerError;
+use crate::journal::diff;
+use crate::storage::Storage;
+use crate::task::{Session, Task};
+
+const DATABASE_FILE: &str = "tasks.sqlite";
+// Stored in the user_version pragma, 0 being a database that was just created.
+const SCHEMA_VERSION: u64 = 4;
+
+const SCHEMA: &str = "
+    CREATE TABLE tasks (
+        list TEXT NOT NULL,
+        id INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        adjustment_seconds INTEGER NOT NULL,
+        running INTEGER NOT NULL,
+        last_run INTEGER,
+        project TEXT,
+        PRIMARY KEY (list, id)
+    );
+    CREATE TABLE tags (
+        list TEXT NOT NULL,
+        task_id INTEGER NOT NULL,
+        tag TEXT NOT NULL,
+        PRIMARY KEY (list, task_id, tag),
+        FOREIGN KEY (list, task_id) REFERENCES tasks (list, id) ON DELETE CASCADE
+    );
+    CREATE TABLE sessions (
+        list TEXT NOT NULL,
+        task_id INTEGER NOT NULL,
+        start_time INTEGER NOT NULL,
+        end_time INTEGER NOT NULL,
+        FOREIGN KEY (list, task_id) REFERENCES tasks (list, id) ON DELETE CASCADE
+    );
+    CREATE INDEX sessions_by_task ON sessions (list, task_id);
+    CREATE INDEX sessions_by_start ON sessions (start_time);
+";
+
+// Upgrades from each version to the next, starting with version 1. New databases are
+// created with version 1 of the schema and upgraded too.
+const MIGRATIONS: [&str; 3] = [
+    // Clock readings of the running session, as JSON.
+    "ALTER TABLE tasks ADD COLUMN start_mark TEXT;",
+    // End times of completed pomodoros.
+    "CREATE TABLE pomodoros (
+        list TEXT NOT NULL,
+        task_id INTEGER NOT NULL,
+        end_time INTEGER NOT NULL,
+        FOREIGN KEY (list, task_id) REFERENCES tasks (list, id) ON DELETE CASCADE
+    );
+    CREATE INDEX pomodoros_by_task ON pomodoros (list, task_id);",
+    "ALTER TABLE tasks ADD COLUMN estimate_seconds INTEGER;",
+];
+
+/// Every task list in one `tasks.sqlite` database. Saving a list only rewrites the
+/// tasks that changed.
+pub struct SqliteStorage {
+    data_dir: PathBuf,
+    path: PathBuf,
+    connection: Connection,
+    backup_count: usize,
+    backup_interval: Duration,
+}
+
+// Times are stored as nanoseconds since the Unix epoch.
+fn to_nanos(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_nanos() as i64,
+        Err(err) => -(err.duration().as_nanos() as i64),
+    }
+}
+
+fn from_nanos(nanos: i64) -> SystemTime {
+    let duration = Duration::from_nanos(nanos.unsigned_abs());
+    if nanos < 0 {
+        UNIX_EPOCH - duration
+    } else {
+        UNIX_EPOCH + duration
+    }
+}
+
+impl SqliteStorage {
+    /// Opens the database in `data_dir`, creating it when it does not exist yet.
+    pub fn open(data_dir: &Path, backup_count: usize) -> Result<SqliteStorage, TimerError> {
+        fs::create_dir_all(data_dir).map_err(TimerError::io(data_dir))?;
+        let path = data_dir.join(DATABASE_FILE);
+        let database_error = |err: rusqlite::Error| TimerError::Database(path.clone(), err.to_string());
+        let connection = Connection::open(&path).map_err(database_error)?;
+        connection.pragma_update(None, "foreign_keys", true).map_err(database_error)?;
+        let version: u64 = connection.pragma_query_value(None, "user_version", |row| row.get(0)).map_err(database_error)?;
+        if version > SCHEMA_VERSION {
+            return Err(TimerError::UnsupportedVersion(path, version));
+        }
+        if version < SCHEMA_VERSION {
+            let transaction = connection.unchecked_transaction().map_err(database_error)?;
+            if version == 0 {
+                transaction.execute_batch(SCHEMA).map_err(database_error)?;
+            }
+            for migration in &MIGRATIONS[version.max(1) as usize - 1..] {
+                transaction.execute_batch(migration).map_err(database_error)?;
+            }
+            transaction.pragma_update(None, "user_version", SCHEMA_VERSION).map_err(database_error)?;
+            transaction.commit().map_err(database_error)?;
+        }
+        Ok(SqliteStorage {
+            data_dir: data_dir.to_path_buf(),
+            path,
+            connection,
+            backup_count,
+            backup_interval: Duration::ZERO,
+        })
+    }
+
+    /// Backs up saves at most this often, see `backup_due`. Every save is backed up by
+    /// default, which reads the whole list each time.
+    pub fn with_backup_interval(mut self, backup_interval: Duration) -> SqliteStorage {
+        self.backup_interval = backup_interval;
+        self
+    }
+
+    fn database_error(&self) -> impl Fn(rusqlite::Error) -> TimerError + '_ {
+        |err| TimerError::Database(self.path.clone(), err.to_string())
+    }
+
+    // Reads the tasks of a list with the sessions that overlap `from..to` and the
+    // pomodoros that ended in it, in nanoseconds.
+    fn read_tasks(&self, list: &str, from: i64, to: i64) -> rusqlite::Result<HashMap<u32, Task>> {
+        let mut tasks = HashMap::new();
+        let mut statement = self.connection.prepare(
+            "SELECT id, name, adjustment_seconds, running, last_run, project, start_mark, estimate_seconds
+             FROM tasks WHERE list = ?1",
+        )?;
+        let mut rows = statement.query(params![list])?;
+        while let Some(row) = rows.next()? {
+            let id: u32 = row.get(0)?;
+            let last_run: Option<i64> = row.get(4)?;
+            // A mark that cannot be read only turns off the checks when the task stops.
+            let start_mark: Option<String> = row.get(6)?;
+            let task = Task {
+                id,
+                name: row.get(1)?,
+                sessions: Vec::new(),
+                adjustment_seconds: row.get(2)?,
+                running: row.get(3)?,
+                last_run: last_run.map(from_nanos),
+                tags: BTreeSet::new(),
+                project: row.get(5)?,
+                start_mark: start_mark.and_then(|mark| serde_json::from_str(&mark).ok()),
+                pomodoros: Vec::new(),
+                estimate_seconds: row.get(7)?,
+            };
+            tasks.insert(id, task);
+        }
+
+        let mut statement = self.connection.prepare("SELECT task_id, tag FROM tags WHERE list = ?1")?;
+        let mut rows = statement.query(params![list])?;
+        while let Some(row) = rows.next()? {
+            if let Some(task) = tasks.get_mut(&row.get(0)?) {
+                task.tags.insert(row.get(1)?);
+            }
+        }
+
+        let mut statement = self.connection.prepare(
+            "SELECT task_id, start_time, end_time FROM sessions
+             WHERE list = ?1 AND start_time < ?3 AND end_time > ?2 ORDER BY start_time",
+        )?;
+        let mut rows = statement.query(params![list, from, to])?;
+        while let Some(row) = rows.next()? {
+            if let Some(task) = tasks.get_mut(&row.get(0)?) {
+                let session = Session {
+                    start: from_nanos(row.get(1)?),
+                    end: from_nanos(row.get(2)?),
+                };
+                task.sessions.push(session);
+            }
+        }
+
+        let mut statement = self.connection.prepare(
+            "SELECT task_id, end_time FROM pomodoros WHERE list = ?1 AND end_time >= ?2 AND end_time < ?3 ORDER BY end_time",
+        )?;
+        let mut rows = statement.query(params![list, from, to])?;
+        while let Some(row) = rows.next()? {
+            if let Some(task) = tasks.get_mut(&row.get(0)?) {
+                task.pomodoros.push(from_nanos(row.get(1)?));
+            }
+        }
+        Ok(tasks)
+    }
+
+    // Replaces the tasks `ids` with their version in `tasks`, deleting those it does not have.
+    // Deleting a task removes its tags, sessions and pomodoros too, changed tasks are inserted again.
+    fn write_tasks(&self, list: &str, tasks: &HashMap<u32, Task>, ids: &[u32]) -> rusqlite::Result<()> {
+        let transaction = self.connection.unchecked_transaction()?;
+        for id in ids.iter().collect::<BTreeSet<&u32>>() {
+            transaction.execute("DELETE FROM tasks WHERE list = ?1 AND id = ?2", params![list, id])?;
+            let Some(task) = tasks.get(id) else {
+                continue;
+            };
+            transaction.execute(
+                "INSERT INTO tasks (list, id, name, adjustment_seconds, running, last_run, project, start_mark, estimate_seconds)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![
+                    list,
+                    id,
+                    task.name,
+                    task.adjustment_seconds,
+                    task.running,
+                    task.last_run.map(to_nanos),
+                    task.project,
+                    task.start_mark.as_ref().and_then(|mark| serde_json::to_string(mark).ok()),
+                    task.estimate_seconds
+                ],
+            )?;
+            for tag in &task.tags {
+                transaction.execute(
+                    "INSERT INTO tags (list, task_id, tag) VALUES (?1, ?2, ?3)",
+                    params![list, id, tag],
+                )?;
+            }
+            for session in &task.sessions {
+                transaction.execute(
+                    "INSERT INTO sessions (list, task_id, start_time, end_time) VALUES (?1, ?2, ?3, ?4)",
+                    params![list, id, to_nanos(session.start), to_nanos(session.end)],
+                )?;
+            }
+            for end in &task.pomodoros {
+                transaction.execute(
+                    "INSERT INTO pomodoros (list, task_id, end_time) VALUES (?1, ?2, ?3)",
+                    params![list, id, to_nanos(*end)],
+                )?;
+            }
+        }
+        transaction.commit()
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn data_dir(&self) -> &Path {
+        &self.data_dir
+    }
+
+    fn location(&self, list: &str) -> String {
+        format!("{DATABASE_FILE} ({list})")
+    }
+
+    fn list_names(&self) -> Result<Vec<String>, TimerError> {
+        let mut names = vec![String::from("current"), String::from("archive")];
+        let mut statement = self
+            .connection
+            .prepare("SELECT DISTINCT list FROM tasks ORDER BY list")
+            .map_err(self.database_error())?;
+        let others = statement
+            .query_map([], |row| row.get::<_, String>(0))
+            .and_then(|rows| rows.collect::<rusqlite::Result<Vec<String>>>())
+            .map_err(self.database_error())?;
+        for name in others {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        Ok(names)
+    }
+
+    fn load_tasks(&self, list: &str) -> Result<HashMap<u32, Task>, TimerError> {
+        self.read_tasks(list, i64::MIN, i64::MAX).map_err(self.database_error())
+    }
+
+    fn save_tasks(&self, list: &str, tasks: &HashMap<u32, Task>) -> Result<(), TimerError> {
+        let before = self.load_tasks(list)?;
+        let ids: Vec<u32> = diff(list, &before, tasks).iter().map(|change| change.id).collect();
+        if ids.is_empty() {
+            return Ok(());
+        }
+        if backup_due(&self.data_dir, list, self.backup_interval)? {
+            backup_tasks(&self.data_dir, list, &before, self.backup_count)?;
+        }
+        self.write_tasks(list, tasks, &ids).map_err(self.database_error())
+    }
+
+    // The whole list is only read for the backup.
+    fn save_changed(&self, list: &str, tasks: &HashMap<u32, Task>, ids: &[u32], overwrite: bool) -> Result<(), TimerError> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        if overwrite || backup_due(&self.data_dir, list, self.backup_interval)? {
+            backup_tasks(&self.data_dir, list, &self.load_tasks(list)?, self.backup_count)?;
+        }
+        self.write_tasks(list, tasks, ids).map_err(self.database_error())
+    }
+
+    // The range is applied by the queries, so sessions outside it are never read.
+    fn load_tasks_between(
+        &self,
+        list: &str,
+        from: Option<SystemTime>,
+        to: Option<SystemTime>,
+    ) -> Result<HashMap<u32, Task>, TimerError> {
+        let from = from.map_or(i64::MIN, to_nanos);
+        let to = to.map_or(i64::MAX, to_nanos);
+        self.read_tasks(list, from, to).map_err(self.database_error())
+    }
+
+    fn outdated_version(&self, _list: &str) -> Result<Option<u64>, TimerError> {
+        // The schema is upgraded when the database is opened.
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::clock::{Clock, SystemClock};
+
+    #[test]
+    fn tasks_survive_a_round_trip() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let data_dir = temp_dir.path();
+        let storage = SqliteStorage::open(data_dir, 2).unwrap();
+        let now = SystemTime::now();
+        let mut task = Task::new(1, "my task");
+        task.sessions.push(Session { start: now - Duration::new(60, 0), end: now });
+        task.tags.insert(String::from("review"));
+        task.project = Some(String::from("acme/backend"));
+        task.running = true;
+        task.last_run = Some(now);
+        task.start_mark = SystemClock.mark();
+        task.pomodoros.push(now - Duration::new(30, 0));
+        task.estimate_seconds = Some(3600);
+        let mut tasks = HashMap::from([(1, task), (2, Task::new(2, "other"))]);
+        storage.save_tasks("current", &tasks).unwrap();
+        storage.save_tasks("work", &HashMap::from([(1, Task::new(1, "elsewhere"))])).unwrap();
+
+        let loaded = storage.load_tasks("current").unwrap();
+        assert!(diff("current", &tasks, &loaded).is_empty());
+        assert_eq!(vec!["current", "archive", "work"], storage.list_names().unwrap());
+
+        tasks.remove(&2);
+        tasks.get_mut(&1).unwrap().tags.clear();
+        storage.save_tasks("current", &tasks).unwrap();
+        let reopened = SqliteStorage::open(data_dir, 2).unwrap();
+        let loaded = reopened.load_tasks("current").unwrap();
+        assert!(diff("current", &tasks, &loaded).is_empty());
+        assert_eq!("elsewhere", reopened.load_tasks("work").unwrap()[&1].name);
+        assert_eq!(1, crate::backup::list_backups(data_dir).unwrap().len());
+    }
+
+    #[test]
+    fn only_the_given_tasks_are_written() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let storage = SqliteStorage::open(temp_dir.path(), 0).unwrap();
+        let mut tasks = HashMap::from([(1, Task::new(1, "a")), (2, Task::new(2, "b"))]);
+        storage.save_tasks("current", &tasks).unwrap();
+        tasks.get_mut(&1).unwrap().rename("c");
+        tasks.get_mut(&2).unwrap().rename("d");
+        tasks.insert(3, Task::new(3, "e"));
+        storage.save_changed("current", &tasks, &[1, 3], false).unwrap();
+        let names: BTreeSet<String> = storage.load_tasks("current").unwrap().into_values().map(|task| task.name).collect();
+        assert_eq!(BTreeSet::from([String::from("b"), String::from("c"), String::from("e")]), names);
+
+        tasks.remove(&1);
+        storage.save_changed("current", &tasks, &[1], true).unwrap();
+        assert!(!storage.load_tasks("current").unwrap().contains_key(&1));
+    }
+
+    #[test]
+    fn sessions_are_read_within_a_range() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let storage = SqliteStorage::open(temp_dir.path(), 0).unwrap();
+        let at = |hours: u64| UNIX_EPOCH + Duration::from_secs(1_700_000_000 + hours * 3600);
+        let mut task = Task::new(1, "a");
+        for (start, end) in [(0, 1), (2, 4), (5, 6)] {
+            task.sessions.push(Session { start: at(start), end: at(end) });
+        }
+        task.pomodoros.extend([at(1), at(3), at(6)]);
+        storage.save_tasks("current", &HashMap::from([(1, task)])).unwrap();
+
+        let tasks = storage.load_tasks_between("current", Some(at(3)), Some(at(6))).unwrap();
+        let starts: Vec<SystemTime> = tasks[&1].sessions.iter().map(|session| session.start).collect();
+        assert_eq!(vec![at(2), at(5)], starts);
+        assert_eq!(vec![at(3)], tasks[&1].pomodoros);
+        // The JSON backend filters the same way after reading everything.
+        let json = crate::storage::json::JsonStorage::new(temp_dir.path(), 0);
+        json.save_tasks("current", &storage.load_tasks("current").unwrap()).unwrap();
+        let filtered = json.load_tasks_between("current", Some(at(3)), Some(at(6))).unwrap();
+        assert!(diff("current", &tasks, &filtered).is_empty());
+        assert_eq!(3, storage.load_tasks_between("current", None, None).unwrap()[&1].sessions.len());
+    }
+
+    #[test]
+    fn times_before_the_epoch_are_kept() {
+        let time = UNIX_EPOCH - Duration::new(5, 7);
+        assert_eq!(time, from_nanos(to_nanos(time)));
+        let time = UNIX_EPOCH + Duration::new(1_700_000_000, 123);
+        assert_eq!(time, from_nanos(to_nanos(time)));
+    }
+
+    #[test]
+    fn version_1_databases_are_upgraded() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let data_dir = temp_dir.path();
+        let connection = Connection::open(data_dir.join(DATABASE_FILE)).unwrap();
+        connection.execute_batch(SCHEMA).unwrap();
+        connection.pragma_update(None, "user_version", 1).unwrap();
+        connection
+            .execute("INSERT INTO tasks (list, id, name, adjustment_seconds, running) VALUES ('current', 1, 'old', 60, 0)", [])
+            .unwrap();
+        drop(connection);
+
+        let storage = SqliteStorage::open(data_dir, 0).unwrap();
+        let tasks = storage.load_tasks("current").unwrap();
+        assert_eq!("old", tasks[&1].name);
+        assert!(tasks[&1].start_mark.is_none() && tasks[&1].pomodoros.is_empty());
+        assert_eq!(None, tasks[&1].estimate_seconds);
+        let version: u64 = storage.connection.pragma_query_value(None, "user_version", |row| row.get(0)).unwrap();
+        assert_eq!(SCHEMA_VERSION, version);
+    }
+}
@@ -0,0 +1,1115 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use crate::activity::{last_activity, record_activity};
+use crate::backup::{find_backup, list_backups, Backup};
+use crate::clock::{Clock, SystemClock};
+use crate::config::{duration_setting, Config};
+use crate::doctor::{run_doctor, Issue};
+use crate::error::TimerError;
+use crate::idle::{idle_time, IdleDecision, IdleLog, IdleReview, IdleSettings};
+use crate::import::{import_entries, Entry};
+use crate::interruption::{review_session, SessionReview};
+use crate::journal::{diff_tasks, record_changes, Journal, JournalEntry, Snapshot, TaskChange};
+use crate::persistence::{lock_data_dir, resolve_data_dir, validate_list_name};
+use crate::report::DateRange;
+use crate::storage::{self, copy_tasks, Storage, StorageKind};
+use crate::task::{Session, Task, TaskFilter};
+
+/// A task that was created, started or switched to, with the tasks stopped to run it alone.
+pub struct Started {
+    pub task: Task,
+    /// Ids of the tasks that were stopped, sorted.
+    pub stopped: Vec<u32>,
+    /// False when the task was already running.
+    pub started: bool,
+    /// Reviews of the stopped tasks that looked interrupted, sorted by task id. Nobody was
+    /// asked how to end them, so their sessions were capped, see `stop_capped`.
+    pub interrupted: Vec<SessionReview>,
+}
+
+/// What an action of `TaskStore::update` changed: the ids of the tasks of the list it
+/// changed, and the changes it saved to other lists itself. Only these tasks are
+/// compared, saved and journaled, the rest of the list is left alone. `overwrites` marks
+/// changes that remove or overwrite task data, which are always backed up first.
+#[derive(Default)]
+pub struct Changed {
+    pub ids: Vec<u32>,
+    pub other_lists: Vec<TaskChange>,
+    pub overwrites: bool,
+}
+
+impl Changed {
+    pub fn tasks(ids: impl IntoIterator<Item = u32>) -> Changed {
+        Changed { ids: ids.into_iter().collect(), ..Changed::default() }
+    }
+
+    pub fn overwritten(ids: impl IntoIterator<Item = u32>) -> Changed {
+        Changed { overwrites: true, ..Changed::tasks(ids) }
+    }
+}
+
+/// One task list of a data directory. Every change locks the data directory, loads the
+/// list, saves it and records the change in the journal, like a `timer` command does.
+///
+/// ```no_run
+/// use simple_task_timer::clock::Clock;
+/// use simple_task_timer::TaskStore;
+///
+/// let store = TaskStore::open_default()?.with_list("work")?;
+/// let created = store.create("write docs", Vec::new(), None, None, true)?;
+/// store.stop(created.task.id, store.clock().now())?;
+/// # Ok::<(), simple_task_timer::TimerError>(())
+/// ```
+pub struct TaskStore {
+    storage: Box<dyn Storage>,
+    list: String,
+    exclusive_start: bool,
+    command: Option<String>,
+    clock: Box<dyn Clock>,
+    max_session: Duration,
+    idle: IdleSettings,
+}
+
+impl TaskStore {
+    /// Opens the `current` list of `data_dir` with the settings of `config`, and its storage
+    /// unless the data directory was migrated to another one.
+    pub fn open(data_dir: &Path, config: &Config) -> Result<TaskStore, TimerError> {
+        let backup_interval = duration_setting("backup_interval", &config.backup_interval)?;
+        let kind = storage::data_dir_kind(data_dir, config.storage)?;
+        let storage = storage::open(data_dir, kind, config.backup_count, backup_interval)?;
+        let idle = IdleSettings {
+            threshold: duration_setting("idle_threshold", &config.idle_threshold)?,
+            heartbeat: config.idle_heartbeat.clone(),
+        };
+        Ok(TaskStore::new(storage)
+            .with_exclusive_start(config.exclusive_start)
+            .with_max_session(duration_setting("max_session", &config.max_session)?)
+            .with_idle_settings(idle))
+    }
+
+    /// Opens the `current` list where the `timer` command keeps it, following the config
+    /// file and the `TIMER_DATA_DIR` environment variable.
+    pub fn open_default() -> Result<TaskStore, TimerError> {
+        let config = Config::load()?;
+        let data_dir = resolve_data_dir(None, &config)?;
+        TaskStore::open(&data_dir, &config)
+    }
+
+    pub fn new(storage: Box<dyn Storage>) -> TaskStore {
+        TaskStore {
+            storage,
+            list: String::from("current"),
+            exclusive_start: false,
+            command: None,
+            clock: Box::new(SystemClock),
+            max_session: Duration::from_secs(12 * 3600),
+            idle: IdleSettings::default(),
+        }
+    }
+
+    /// Works on another task list, created the first time a task is saved in it.
+    pub fn with_list(mut self, list: &str) -> Result<TaskStore, TimerError> {
+        validate_list_name(list)?;
+        self.list = list.to_string();
+        Ok(self)
+    }
+
+    /// Makes `start` and `create` with start stop every other running task, like `switch`.
+    pub fn with_exclusive_start(mut self, exclusive_start: bool) -> TaskStore {
+        self.exclusive_start = exclusive_start;
+        self
+    }
+
+    /// Describes the following changes in the journal with `command` instead of the
+    /// name of the method, the `timer` command passes its command line.
+    pub fn with_command(mut self, command: &str) -> TaskStore {
+        self.command = Some(command.to_string());
+        self
+    }
+
+    /// Sessions longer than `max_session` are reviewed before they stop, see `review_stop`.
+    pub fn with_max_session(mut self, max_session: Duration) -> TaskStore {
+        self.max_session = max_session;
+        self
+    }
+
+    /// What `watch_idle` counts as being away.
+    pub fn with_idle_settings(mut self, idle: IdleSettings) -> TaskStore {
+        self.idle = idle;
+        self
+    }
+
+    /// Takes the current time from `clock` instead of the system clock, for tests and
+    /// simulations. Keep a clone of a `FakeClock` to move the time forward.
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> TaskStore {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    pub fn clock(&self) -> &dyn Clock {
+        self.clock.as_ref()
+    }
+
+    pub fn list(&self) -> &str {
+        &self.list
+    }
+
+    pub fn storage(&self) -> &dyn Storage {
+        self.storage.as_ref()
+    }
+
+    pub fn tasks(&self) -> Result<HashMap<u32, Task>, TimerError> {
+        self.storage.load_tasks(&self.list)
+    }
+
+    /// Tasks of another list, without switching to it.
+    pub fn tasks_in(&self, list: &str) -> Result<HashMap<u32, Task>, TimerError> {
+        self.storage.load_tasks(list)
+    }
+
+    /// Tasks of the list with only the sessions and pomodoros that fall in `range`, enough
+    /// for `build_report` without reading the rest of their history.
+    pub fn tasks_between(&self, range: DateRange) -> Result<HashMap<u32, Task>, TimerError> {
+        let (from, to) = range.bounds();
+        self.storage.load_tasks_between(&self.list, from, to)
+    }
+
+    pub fn task(&self, id: u32) -> Result<Task, TimerError> {
+        self.tasks()?.remove(&id).ok_or(TimerError::TaskNotFound(id))
+    }
+
+    /// Tasks matching `filter`, sorted by id.
+    pub fn query(&self, filter: &TaskFilter) -> Result<Vec<Task>, TimerError> {
+        let mut tasks: Vec<Task> = self.tasks()?.into_values().filter(|task| filter.matches(task)).collect();
+        tasks.sort_by_key(|task| task.id);
+        Ok(tasks)
+    }
+
+    pub fn list_names(&self) -> Result<Vec<String>, TimerError> {
+        self.storage.list_names()
+    }
+
+    /// Runs `action` on the tasks of the list while holding the data directory lock, then
+    /// saves and journals the tasks it says it changed, along with its changes to other lists.
+    pub fn update<T>(
+        &self,
+        command: &str,
+        action: impl FnOnce(&dyn Storage, &mut HashMap<u32, Task>) -> Result<(T, Changed), TimerError>,
+    ) -> Result<T, TimerError> {
+        let storage = self.storage.as_ref();
+        let _lock = lock_data_dir(storage.data_dir())?;
+        let mut tasks = storage.load_tasks(&self.list)?;
+        let before = tasks.clone();
+        let (result, changed) = action(storage, &mut tasks)?;
+        let mut changes = diff_tasks(&self.list, &before, &tasks, &changed.ids);
+        let ids: Vec<u32> = changes.iter().map(|change| change.id).collect();
+        storage.save_changed(&self.list, &tasks, &ids, changed.overwrites)?;
+        changes.extend(changed.other_lists);
+        record_changes(storage.data_dir(), self.command.as_deref().unwrap_or(command), self.clock.now(), changes)?;
+        record_activity(storage.data_dir(), self.clock.now())?;
+        Ok(result)
+    }
+
+    // Changes one task of the list and returns it. `overwrites` is set for changes that
+    // remove or overwrite some of its data.
+    fn update_task(
+        &self,
+        command: &str,
+        id: u32,
+        overwrites: bool,
+        action: impl FnOnce(&mut Task) -> Result<(), TimerError>,
+    ) -> Result<Task, TimerError> {
+        self.update(command, |_, tasks| {
+            let task = get_task(tasks, &id)?;
+            action(task)?;
+            let changed = if overwrites { Changed::overwritten([id]) } else { Changed::tasks([id]) };
+            Ok((task.clone(), changed))
+        })
+    }
+
+    pub fn create(
+        &self,
+        name: &str,
+        tags: Vec<String>,
+        project: Option<String>,
+        estimate_seconds: Option<u64>,
+        start: bool,
+    ) -> Result<Started, TimerError> {
+        self.update(&format!("create '{name}'"), |storage, tasks| {
+            let id = find_new_unique_id(tasks);
+            let mut task = Task::new(id, name);
+            task.tags.extend(tags);
+            task.project = project;
+            task.estimate_seconds = estimate_seconds;
+            let (mut stopped, mut interrupted) = (Vec::new(), Vec::new());
+            if start {
+                let now = self.clock.now();
+                if self.exclusive_start {
+                    (stopped, interrupted) = self.stop_other_tasks(storage, tasks, id, now)?;
+                }
+                start_task(&mut task, now, self.clock())?;
+            }
+            tasks.insert(id, task.clone());
+            let changed = Changed::tasks(stopped.iter().copied().chain([id]));
+            Ok((Started { task, stopped, started: start, interrupted }, changed))
+        })
+    }
+
+    pub fn delete(&self, id: u32) -> Result<Task, TimerError> {
+        self.update(&format!("delete {id}"), |_, tasks| {
+            let task = tasks.remove(&id).ok_or(TimerError::TaskNotFound(id))?;
+            Ok((task, Changed::overwritten([id])))
+        })
+    }
+
+    /// Deletes the only task called `name`.
+    pub fn delete_by_name(&self, name: &str) -> Result<Task, TimerError> {
+        self.update(&format!("delname '{name}'"), |_, tasks| {
+            let task = delete_task_by_name(tasks, name)?;
+            let changed = Changed::overwritten([task.id]);
+            Ok((task, changed))
+        })
+    }
+
+    /// Starts a task as of `at`, stopping the other running tasks first with exclusive start.
+    pub fn start(&self, id: u32, at: SystemTime) -> Result<Started, TimerError> {
+        if !self.exclusive_start {
+            let task = self.update_task(&format!("start {id}"), id, false, |task| start_task(task, at, self.clock()))?;
+            return Ok(Started { task, stopped: Vec::new(), started: true, interrupted: Vec::new() });
+        }
+        self.update(&format!("start {id}"), |storage, tasks| {
+            if get_task(tasks, &id)?.running {
+                return Err(TimerError::AlreadyRunning(id));
+            }
+            self.switch_task(storage, tasks, id, at)
+        })
+    }
+
+    /// Starts a task as of `at` after stopping every other running task.
+    pub fn switch(&self, id: u32, at: SystemTime) -> Result<Started, TimerError> {
+        self.update(&format!("switch {id}"), |storage, tasks| self.switch_task(storage, tasks, id, at))
+    }
+
+    // Starts the task as of `at` after stopping every other running task.
+    fn switch_task(
+        &self,
+        storage: &dyn Storage,
+        tasks: &mut HashMap<u32, Task>,
+        id: u32,
+        at: SystemTime,
+    ) -> Result<(Started, Changed), TimerError> {
+        get_task(tasks, &id)?;
+        let (stopped, interrupted) = self.stop_other_tasks(storage, tasks, id, at)?;
+        let task = get_task(tasks, &id)?;
+        let started = !task.running;
+        if started {
+            start_task(task, at, self.clock())?;
+        }
+        let changed = Changed::tasks(stopped.iter().copied().chain([id]));
+        Ok((Started { task: task.clone(), stopped, started, interrupted }, changed))
+    }
+
+    // Stops every running task except `id` as of `at` and returns the ids that were
+    // stopped, with the reviews of those that looked interrupted.
+    fn stop_other_tasks(
+        &self,
+        storage: &dyn Storage,
+        tasks: &mut HashMap<u32, Task>,
+        id: u32,
+        at: SystemTime,
+    ) -> Result<(Vec<u32>, Vec<SessionReview>), TimerError> {
+        let last_activity = last_activity(storage.data_dir())?;
+        let mut stopped = Vec::new();
+        let mut interrupted = Vec::new();
+        for task in tasks.values_mut().filter(|task| task.running && task.id != id) {
+            interrupted.extend(self.stop_capped_at(task, at, last_activity)?);
+            stopped.push(task.id);
+        }
+        stopped.sort();
+        interrupted.sort_by_key(|review| review.task_id);
+        Ok((stopped, interrupted))
+    }
+
+    // Stops the task as of `at`, ending a session that looks interrupted at its capped
+    // length. Returns the review in that case.
+    fn stop_capped_at(
+        &self,
+        task: &mut Task,
+        at: SystemTime,
+        last_activity: Option<SystemTime>,
+    ) -> Result<Option<SessionReview>, TimerError> {
+        let now = self.clock.now();
+        let mark = self.clock.mark();
+        let review = review_session(task, at, now, mark.as_ref(), self.max_session, last_activity);
+        match &review {
+            Some(review) => task.end_session(review.capped.clone(), now)?,
+            None => task.stop_at(at, now)?,
+        }
+        Ok(review)
+    }
+
+    pub fn stop(&self, id: u32, at: SystemTime) -> Result<Task, TimerError> {
+        let now = self.clock.now();
+        self.update_task(&format!("stop {id}"), id, false, |task| task.stop_at(at, now))
+    }
+
+    /// Checks whether the running task was interrupted by a suspend, a clock change or a
+    /// restart, or ran implausibly long, as if it stopped at `at`. `None` means `stop` can
+    /// be used as it is.
+    pub fn review_stop(&self, id: u32, at: SystemTime) -> Result<Option<SessionReview>, TimerError> {
+        let task = self.task(id)?;
+        let last_activity = self.last_activity()?;
+        let mark = self.clock.mark();
+        Ok(review_session(&task, at, self.clock.now(), mark.as_ref(), self.max_session, last_activity))
+    }
+
+    /// Stops a task like `stop`, except that a session that looks interrupted, see
+    /// `review_stop`, only keeps the time the machine was awake, at most the longest
+    /// plausible session. For callers that cannot ask how to end it, the review is returned
+    /// to tell the user what happened.
+    pub fn stop_capped(&self, id: u32, at: SystemTime) -> Result<(Task, Option<SessionReview>), TimerError> {
+        self.update(&format!("stop {id}"), |storage, tasks| {
+            let last_activity = last_activity(storage.data_dir())?;
+            let task = get_task(tasks, &id)?;
+            let review = self.stop_capped_at(task, at, last_activity)?;
+            Ok(((task.clone(), review), Changed::tasks([id])))
+        })
+    }
+
+    /// Stops a task with one of the sessions offered by `review_stop`.
+    pub fn end_session(&self, id: u32, session: Session) -> Result<Task, TimerError> {
+        let now = self.clock.now();
+        self.update_task(&format!("stop {id}"), id, false, |task| task.end_session(session, now))
+    }
+
+    /// Stops a task at the end of a pomodoro's work interval and counts the pomodoro. A
+    /// session that looks interrupted is capped like with `stop_capped`.
+    pub fn finish_pomodoro(&self, id: u32) -> Result<(Task, Option<SessionReview>), TimerError> {
+        let now = self.clock.now();
+        self.update(&format!("pomodoro {id}"), |storage, tasks| {
+            let last_activity = last_activity(storage.data_dir())?;
+            let task = get_task(tasks, &id)?;
+            let review = self.stop_capped_at(task, now, last_activity)?;
+            task.pomodoros.push(now);
+            Ok(((task.clone(), review), Changed::tasks([id])))
+        })
+    }
+
+    /// Records a session that was not tracked live.
+    pub fn log(&self, id: u32, session: Session) -> Result<Task, TimerError> {
+        let now = self.clock.now();
+        self.update_task(&format!("log {id}"), id, false, |task| task.log(session, now))
+    }
+
+    pub fn rename(&self, id: u32, name: &str) -> Result<Task, TimerError> {
+        self.update_task(&format!("rename {id} '{name}'"), id, true, |task| {
+            task.rename(name);
+            Ok(())
+        })
+    }
+
+    pub fn tag(&self, id: u32, tags: Vec<String>) -> Result<Task, TimerError> {
+        self.update_task(&format!("tag {id}"), id, false, |task| {
+            task.tags.extend(tags);
+            Ok(())
+        })
+    }
+
+    pub fn untag(&self, id: u32, tags: &[String]) -> Result<Task, TimerError> {
+        self.update_task(&format!("untag {id}"), id, true, |task| {
+            for tag in tags {
+                task.tags.remove(tag);
+            }
+            Ok(())
+        })
+    }
+
+    /// Moves a task to a project, or out of its project with `None`.
+    pub fn set_project(&self, id: u32, project: Option<String>) -> Result<Task, TimerError> {
+        self.update_task(&format!("project {id}"), id, true, |task| {
+            task.project = project;
+            Ok(())
+        })
+    }
+
+    /// Sets how long a task is expected to take, or removes its estimate.
+    pub fn set_estimate(&self, id: u32, estimate_seconds: Option<u64>) -> Result<Task, TimerError> {
+        self.update_task(&format!("estimate {id}"), id, true, |task| {
+            task.estimate_seconds = estimate_seconds;
+            Ok(())
+        })
+    }
+
+    /// Adds a duration such as `1h30m` to a task.
+    pub fn add_time(&self, id: u32, time: &str) -> Result<Task, TimerError> {
+        self.update_task(&format!("add {id} {time}"), id, false, |task| task.add_time(time))
+    }
+
+    pub fn subtract_time(&self, id: u32, time: &str) -> Result<Task, TimerError> {
+        self.update_task(&format!("sub {id} {time}"), id, true, |task| task.subtract_time(time))
+    }
+
+    pub fn set_time(&self, id: u32, time: &str) -> Result<Task, TimerError> {
+        self.update_task(&format!("set {id} {time}"), id, true, |task| task.set_time(time))
+    }
+
+    /// Moves a stopped task to the archive and returns it with its archive id.
+    pub fn archive(&self, id: u32) -> Result<Task, TimerError> {
+        if self.list == "archive" {
+            return Err(TimerError::InvalidArgument(String::from("Cannot archive archived tasks")));
+        }
+        self.update(&format!("archive {id}"), |storage, tasks| move_to_list(storage, tasks, &id, "archive"))
+    }
+
+    /// Moves an archived task back to this list and returns it with its new id.
+    pub fn unarchive(&self, id: u32) -> Result<Task, TimerError> {
+        if self.list == "archive" {
+            return Err(TimerError::InvalidArgument(String::from(
+                "Select the list to move the task to with --tasktype, it cannot be archive",
+            )));
+        }
+        self.update(&format!("unarchive {id}"), |storage, tasks| {
+            let mut archived_tasks = storage.load_tasks("archive")?;
+            let archived = archived_tasks.clone();
+            let task = transfer_task(&mut archived_tasks, tasks, &id, "unarchiving")?;
+            storage.save_changed("archive", &archived_tasks, &[id], true)?;
+            let changed = Changed {
+                ids: vec![task.id],
+                other_lists: diff_tasks("archive", &archived, &archived_tasks, &[id]),
+                overwrites: false,
+            };
+            Ok((task, changed))
+        })
+    }
+
+    /// Moves a stopped task to another list and returns it with its id there.
+    pub fn move_to(&self, id: u32, list: &str) -> Result<Task, TimerError> {
+        validate_list_name(list)?;
+        if list == self.list {
+            return Err(TimerError::InvalidArgument(format!("Task {id} is already in {list}")));
+        }
+        self.update(&format!("move {id} --to {list}"), |storage, tasks| move_to_list(storage, tasks, &id, list))
+    }
+
+    /// Deletes every task of the list and returns how many there were.
+    pub fn clear(&self) -> Result<usize, TimerError> {
+        self.update("clear", |_, tasks| {
+            let changed = Changed::overwritten(tasks.keys().copied());
+            let cleared = tasks.len();
+            tasks.clear();
+            Ok((cleared, changed))
+        })
+    }
+
+    /// Adds entries read from another tracker and returns the tasks they went to, sorted by id.
+    /// With `exclusive_start`, at most one task of the list may run afterwards.
+    pub fn import(&self, entries: Vec<Entry>) -> Result<Vec<Task>, TimerError> {
+        self.update("import", |_, tasks| {
+            let ids = import_entries(tasks, entries, self.exclusive_start, self.clock.now())?;
+            let imported = ids.iter().map(|id| tasks[id].clone()).collect();
+            Ok((imported, Changed::tasks(ids)))
+        })
+    }
+
+    /// Undoes the last journaled change, or redoes the last undone one, and returns it.
+    pub fn step_history(&self, undo: bool) -> Result<JournalEntry, TimerError> {
+        let data_dir = self.storage.data_dir();
+        let _lock = lock_data_dir(data_dir)?;
+        let mut journal = Journal::load(data_dir)?;
+        let entry = if undo { journal.undo(self.storage())? } else { journal.redo(self.storage())? };
+        journal.save(data_dir)?;
+        record_activity(data_dir, self.clock.now())?;
+        Ok(entry)
+    }
+
+    /// The last time tasks of the data directory were changed, which tells when the user was
+    /// last around. Commands that only read tasks do not count, or running `timer list`
+    /// after a suspend would hide how long the machine was away.
+    pub fn last_activity(&self) -> Result<Option<SystemTime>, TimerError> {
+        let data_dir = self.storage.data_dir();
+        let _lock = lock_data_dir(data_dir)?;
+        last_activity(data_dir)
+    }
+
+    /// Samples the idle time once, for `timer idle watch`. While a task of any list runs and
+    /// the user has been away longer than the idle threshold, the break is recorded and
+    /// returned.
+    pub fn watch_idle(&self) -> Result<Option<Session>, TimerError> {
+        let data_dir = self.storage.data_dir();
+        let _lock = lock_data_dir(data_dir)?;
+        let mut running = false;
+        for list in self.storage.list_names()? {
+            running |= self.storage.load_tasks(&list)?.values().any(|task| task.running);
+        }
+        let now = self.clock.now();
+        let idle = idle_time(data_dir, &self.idle, now)?;
+        let Some(idle) = idle.filter(|idle| running && *idle >= self.idle.threshold) else {
+            return Ok(None);
+        };
+        let mut log = IdleLog::load(data_dir)?;
+        let period = log.record(idle, now);
+        log.save(data_dir)?;
+        Ok(Some(period))
+    }
+
+    /// Recorded idle periods during which tasks of the list ran and that the list did not
+    /// review yet, oldest first.
+    pub fn idle_reviews(&self) -> Result<Vec<IdleReview>, TimerError> {
+        let tasks = self.tasks()?;
+        let now = self.clock.now();
+        let mut reviews = Vec::new();
+        for period in IdleLog::load(self.storage.data_dir())?.periods {
+            if period.reviewed.contains(&self.list) {
+                continue;
+            }
+            let overlaps = idle_overlaps(&tasks, &period.session, now);
+            if !overlaps.is_empty() {
+                reviews.push(IdleReview { period: period.session, tasks: overlaps });
+            }
+        }
+        Ok(reviews)
+    }
+
+    /// Applies `decision` to the tasks of the list that ran during an idle period. The
+    /// period is forgotten once every list with tasks that ran during it has been reviewed.
+    /// Returns how many seconds were discarded or moved.
+    pub fn resolve_idle(&self, period: &Session, decision: IdleDecision) -> Result<u64, TimerError> {
+        let command = match decision {
+            IdleDecision::Discard => String::from("idle discard"),
+            IdleDecision::Keep => String::from("idle keep"),
+            IdleDecision::Reassign(id) => format!("idle reassign {id}"),
+        };
+        let now = self.clock.now();
+        self.update(&command, |storage, tasks| {
+            let mut removed = Vec::new();
+            let mut changed = Changed::default();
+            if decision != IdleDecision::Keep {
+                changed.overwrites = true;
+                if let IdleDecision::Reassign(id) = decision {
+                    get_task(tasks, &id)?;
+                    changed.ids.push(id);
+                }
+                // The target loses its own time of the period too, it gets it back merged.
+                for task in tasks.values_mut() {
+                    let discarded = task.discard(period, now);
+                    if !discarded.is_empty() {
+                        changed.ids.push(task.id);
+                    }
+                    removed.extend(discarded);
+                }
+                if let IdleDecision::Reassign(id) = decision {
+                    for session in merge_sessions(removed.clone()) {
+                        get_task(tasks, &id)?.log(session, now)?;
+                    }
+                }
+            }
+            let mut log = IdleLog::load(storage.data_dir())?;
+            let reviewed = log.mark_reviewed(period, &self.list);
+            let mut pending = false;
+            for list in storage.list_names()? {
+                if !reviewed.contains(&list) {
+                    pending |= !idle_overlaps(&storage.load_tasks(&list)?, period, now).is_empty();
+                }
+            }
+            if !pending {
+                log.remove(period);
+            }
+            log.save(storage.data_dir())?;
+            Ok((removed.iter().map(Session::duration).sum(), changed))
+        })
+    }
+
+    /// Every backup of the data directory, oldest first.
+    pub fn backups(&self) -> Result<Vec<Backup>, TimerError> {
+        list_backups(self.storage.data_dir())
+    }
+
+    /// Replaces a list with one of its backups and returns the backup and its number of tasks.
+    pub fn restore_backup(&self, name: &str) -> Result<(Backup, usize), TimerError> {
+        let storage = self.storage.as_ref();
+        let _lock = lock_data_dir(storage.data_dir())?;
+        let backup = find_backup(storage.data_dir(), name)?;
+        let tasks = backup.load()?;
+        // Journaled like other changes, so a restore can be undone.
+        let snapshot = Snapshot::take(storage, &[&backup.list])?;
+        let ids: Vec<u32> = storage.load_tasks(&backup.list)?.keys().chain(tasks.keys()).copied().collect();
+        storage.save_changed(&backup.list, &tasks, &ids, true)?;
+        let command = self.command.clone().unwrap_or(format!("backup restore {name}"));
+        snapshot.record(storage, &command, self.clock.now())?;
+        record_activity(storage.data_dir(), self.clock.now())?;
+        Ok((backup, tasks.len()))
+    }
+
+    /// Checks every list and the journal for problems, repairing them when `fix` is set.
+    pub fn doctor(&self, fix: bool) -> Result<Vec<Issue>, TimerError> {
+        let _lock = lock_data_dir(self.storage.data_dir())?;
+        run_doctor(self.storage(), fix, self.command.as_deref().unwrap_or("doctor"), self.clock.now())
+    }
+
+    /// Copies every list into the `to` storage of the same data directory, which must not
+    /// contain any tasks yet, and switches the data directory to it. Returns the number of
+    /// lists and tasks copied.
+    pub fn copy_to_storage(&self, to: StorageKind) -> Result<(usize, usize), TimerError> {
+        let data_dir = self.storage.data_dir();
+        let _lock = lock_data_dir(data_dir)?;
+        // Nothing is overwritten, so there is nothing to back up.
+        let target = storage::open(data_dir, to, 0, Duration::ZERO)?;
+        let copied = copy_tasks(self.storage(), target.as_ref())?;
+        storage::set_data_dir_kind(data_dir, to)?;
+        Ok(copied)
+    }
+}
+
+// The tasks that ran during `period`, by id, with how many seconds they ran in it.
+fn idle_overlaps(tasks: &HashMap<u32, Task>, period: &Session, now: SystemTime) -> Vec<(u32, u64)> {
+    let mut ids: Vec<&u32> = tasks.keys().collect();
+    ids.sort();
+    ids.into_iter()
+        .map(|id| (*id, overlap(&tasks[id].sessions_until(now), period)))
+        .filter(|(_, seconds)| *seconds > 0)
+        .collect()
+}
+
+// Seconds of `sessions` within `period`.
+fn overlap(sessions: &[Session], period: &Session) -> u64 {
+    sessions
+        .iter()
+        .filter(|session| session.start < period.end && period.start < session.end)
+        .map(|session| Session { start: session.start.max(period.start), end: session.end.min(period.end) }.duration())
+        .sum()
+}
+
+// Joins overlapping sessions, so time several tasks shared is only counted once.
+fn merge_sessions(mut sessions: Vec<Session>) -> Vec<Session> {
+    sessions.sort_by_key(|session| session.start);
+    let mut merged: Vec<Session> = Vec::new();
+    for session in sessions {
+        match merged.last_mut() {
+            Some(last) if session.start <= last.end => last.end = last.end.max(session.end),
+            _ => merged.push(session),
+        }
+    }
+    merged
+}
+
+pub fn find_new_unique_id(tasks: &HashMap<u32, Task>) -> u32 {
+    tasks.keys()
+        .copied()
+        .max()
+        .unwrap_or_default() + 1
+}
+
+pub fn get_task<'a>(tasks: &'a mut HashMap<u32, Task>, task_id: &u32) -> Result<&'a mut Task, TimerError> {
+    tasks.get_mut(task_id).ok_or(TimerError::TaskNotFound(*task_id))
+}
+
+fn delete_task_by_name(tasks: &mut HashMap<u32, Task>, task_name: &str) -> Result<Task, TimerError> {
+    let filter: Vec<u32> = tasks.values()
+        .filter(|t| t.name.eq(task_name))
+        .map(|t| t.id)
+        .collect();
+    match filter.as_slice() {
+        [] => Err(TimerError::TaskNameNotFound(String::from(task_name))),
+        [id] => Ok(tasks.remove(id).unwrap()),
+        _ => Err(TimerError::AmbiguousTaskName(String::from(task_name))),
+    }
+}
+
+// Starts the task as of `at`, keeping the clock readings of that moment to detect
+// suspends and clock changes when it stops.
+fn start_task(task: &mut Task, at: SystemTime, clock: &dyn Clock) -> Result<(), TimerError> {
+    let now = clock.now();
+    task.start_at(at, now)?;
+    task.start_mark = clock.mark().and_then(|mark| mark.before(now.duration_since(at).unwrap_or_default()));
+    Ok(())
+}
+
+/// Moves a stopped task from `tasks` to `target` and returns it with its new id there.
+pub fn transfer_task(
+    tasks: &mut HashMap<u32, Task>,
+    target: &mut HashMap<u32, Task>,
+    task_id: &u32,
+    action: &'static str,
+) -> Result<Task, TimerError> {
+    let task = get_task(tasks, task_id)?;
+    if task.running {
+        return Err(TimerError::TaskRunning(*task_id, action));
+    }
+    let mut moved = task.clone();
+    moved.id = find_new_unique_id(target);
+    target.insert(moved.id, moved.clone());
+    tasks.remove(task_id);
+    Ok(moved)
+}
+
+/// Moves a stopped task into another saved list. The target list is saved right away,
+/// the caller saves `tasks`.
+pub fn move_to_list(
+    storage: &dyn Storage,
+    tasks: &mut HashMap<u32, Task>,
+    task_id: &u32,
+    list: &str,
+) -> Result<(Task, Changed), TimerError> {
+    let mut target = storage.load_tasks(list)?;
+    let action = if list == "archive" { "archiving" } else { "moving" };
+    let moved = transfer_task(tasks, &mut target, task_id, action)?;
+    storage.save_changed(list, &target, &[moved.id], false)?;
+    let changed = Changed {
+        ids: vec![*task_id],
+        other_lists: vec![TaskChange { list: list.to_string(), id: moved.id, before: None, after: Some(moved.clone()) }],
+        overwrites: true,
+    };
+    Ok((moved, changed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    use chrono::{DateTime, Datelike, Local, TimeZone};
+    use tempfile::TempDir;
+
+    use crate::clock::FakeClock;
+    use crate::interruption::{Interruption, Resolution};
+    use crate::report::{build_report, DateRange, Grouping};
+    use crate::storage::json::JsonStorage;
+
+    // The data directory is deleted when the returned `TempDir` is dropped.
+    fn temp_store() -> (TempDir, TaskStore) {
+        let data_dir = tempfile::tempdir().unwrap();
+        let store = TaskStore::new(Box::new(JsonStorage::new(data_dir.path(), 0)));
+        (data_dir, store)
+    }
+
+    #[test]
+    fn stores_can_move_to_other_threads() {
+        let (_data_dir, store) = temp_store();
+        let store = store.with_clock(FakeClock::new(local(4, 9, 0)));
+        let created = std::thread::spawn(move || store.create("elsewhere", Vec::new(), None, None, false));
+        assert_eq!(1, created.join().unwrap().unwrap().task.id);
+    }
+
+    #[test]
+    fn unique_id() {
+        let mut tasks = HashMap::new();
+        tasks.insert(1, Task::new(1, "my task"));
+        let id = find_new_unique_id(&tasks);
+        assert_eq!(id, 2);
+    }
+
+    #[test]
+    fn delete_missing_task_is_an_error() {
+        let (_data_dir, store) = temp_store();
+        store.create("my task", Vec::new(), None, None, false).unwrap();
+        let err = store.delete(2).unwrap_err();
+        assert_eq!(3, err.exit_code());
+        let err = store.delete_by_name("other").unwrap_err();
+        assert_eq!(3, err.exit_code());
+        assert_eq!(1, store.tasks().unwrap().len());
+    }
+
+    #[test]
+    fn switch_stops_other_tasks() {
+        let now = SystemTime::now();
+        let mut tasks = HashMap::new();
+        for id in 1..=3 {
+            let mut task = Task::new(id, "my task");
+            task.start(now).unwrap();
+            tasks.insert(id, task);
+        }
+        tasks.insert(4, Task::new(4, "my task"));
+        let (_data_dir, store) = temp_store();
+        let store = store.with_clock(FakeClock::new(now));
+        let (started, changed) = store.switch_task(store.storage(), &mut tasks, 4, now).unwrap();
+        assert_eq!(vec![1, 2, 3], started.stopped);
+        assert_eq!(vec![1, 2, 3, 4], changed.ids);
+        let running: Vec<u32> = tasks.values().filter(|t| t.running).map(|t| t.id).collect();
+        assert_eq!(vec![4], running);
+        assert_eq!(1, tasks[&2].sessions.len());
+    }
+
+    #[test]
+    fn transfer_task_assigns_new_id() {
+        let mut tasks = HashMap::from([(1, Task::new(1, "my task")), (2, Task::new(2, "running"))]);
+        let mut target = HashMap::from([(1, Task::new(1, "other"))]);
+        let moved = transfer_task(&mut tasks, &mut target, &1, "moving").unwrap();
+        assert_eq!(2, moved.id);
+        assert_eq!("my task", target[&2].name);
+        assert!(!tasks.contains_key(&1));
+
+        tasks.get_mut(&2).unwrap().start(SystemTime::now()).unwrap();
+        let result = transfer_task(&mut tasks, &mut target, &2, "moving");
+        assert!(matches!(result, Err(TimerError::TaskRunning(2, "moving"))));
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn migrating_a_data_dir_leaves_other_data_dirs_alone() {
+        let (migrated, other) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+        let config = Config::default();
+        for data_dir in [&migrated, &other] {
+            let store = TaskStore::open(data_dir.path(), &config).unwrap();
+            store.create("kept", Vec::new(), None, None, false).unwrap();
+        }
+
+        let store = TaskStore::open(migrated.path(), &config).unwrap();
+        assert_eq!((1, 1), store.copy_to_storage(StorageKind::Sqlite).unwrap());
+        // The JSON file is left behind, so only the SQLite database sees the new task.
+        let store = TaskStore::open(migrated.path(), &config).unwrap();
+        store.create("after", Vec::new(), None, None, false).unwrap();
+        assert!(store.storage().location("current").starts_with("tasks.sqlite"));
+        assert_eq!(2, store.tasks().unwrap().len());
+
+        let store = TaskStore::open(other.path(), &config).unwrap();
+        assert!(store.storage().location("current").ends_with("current.json"));
+        assert_eq!(1, store.tasks().unwrap().len());
+    }
+
+    #[test]
+    fn every_save_is_backed_up_by_default() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let store = TaskStore::new(Box::new(JsonStorage::new(data_dir.path(), 10)));
+        store.create("a", Vec::new(), None, None, false).unwrap();
+        store.create("b", Vec::new(), None, None, false).unwrap();
+        store.create("c", Vec::new(), None, None, false).unwrap();
+        // Nothing to back up before the first save.
+        assert_eq!(2, store.backups().unwrap().len());
+        store.delete(2).unwrap();
+        let backups = store.backups().unwrap();
+        assert_eq!(3, backups.len());
+        assert_eq!(3, backups[2].load().unwrap().len());
+    }
+
+    #[test]
+    fn throttled_backups_still_cover_removed_data() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let storage = JsonStorage::new(data_dir.path(), 10).with_backup_interval(Duration::from_secs(3600));
+        let store = TaskStore::new(Box::new(storage));
+        for name in ["a", "b", "c"] {
+            store.create(name, Vec::new(), None, None, false).unwrap();
+        }
+        store.add_time(1, "1h").unwrap();
+        assert_eq!(1, store.backups().unwrap().len());
+        store.subtract_time(1, "10m").unwrap();
+        store.delete(2).unwrap();
+        assert_eq!(3, store.backups().unwrap().len());
+        store.clear().unwrap();
+        let backups = store.backups().unwrap();
+        assert_eq!(4, backups.len());
+        assert_eq!(2, backups[3].load().unwrap().len());
+        store.restore_backup(&backups[3].name).unwrap();
+        assert_eq!(2, store.tasks().unwrap().len());
+        assert_eq!(5, store.backups().unwrap().len());
+    }
+
+    #[test]
+    fn unique_id_empty_tasks() {
+        let tasks = HashMap::new();
+        let id = find_new_unique_id(&tasks);
+        assert_eq!(id, 1);
+    }
+
+    #[test]
+    fn store_changes_are_saved_and_journaled() {
+        let (_data_dir, store) = temp_store();
+        let store = store.with_exclusive_start(true);
+        let first = store.create("first", Vec::new(), None, None, true).unwrap();
+        let second = store.create("second", Vec::new(), None, None, true).unwrap();
+        assert_eq!(vec![first.task.id], second.stopped);
+        assert!(matches!(store.start(second.task.id, SystemTime::now()), Err(TimerError::AlreadyRunning(2))));
+
+        store.stop(second.task.id, SystemTime::now()).unwrap();
+        let archived = store.archive(second.task.id).unwrap();
+        assert_eq!(1, archived.id);
+        assert_eq!(vec!["first"], store.query(&TaskFilter::default()).unwrap().iter().map(|t| &t.name).collect::<Vec<_>>());
+        assert_eq!("second", store.tasks_in("archive").unwrap()[&1].name);
+
+        let entry = store.step_history(true).unwrap();
+        assert_eq!("archive 2", entry.command);
+        assert!(store.tasks_in("archive").unwrap().is_empty());
+        assert_eq!(2, store.tasks().unwrap().len());
+    }
+
+    // Days in early March, away from daylight saving changes in most time zones.
+    fn local(day: u32, hour: u32, minute: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(2024, 3, day, hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn simulated_days_are_tracked_and_reported() {
+        let clock = FakeClock::new(local(4, 9, 0));
+        let (_data_dir, store) = temp_store();
+        let store = store.with_clock(clock.clone());
+        let id = store.create("report", Vec::new(), None, None, true).unwrap().task.id;
+        clock.advance(Duration::from_secs(3 * 3600));
+        store.stop(id, clock.now()).unwrap();
+
+        // Started late the next day and still running after midnight.
+        clock.set(local(5, 22, 30));
+        store.start(id, clock.now()).unwrap();
+        clock.set(local(6, 1, 0));
+        let now = clock.now();
+        assert_eq!(10800 + 9000, store.task(id).unwrap().current_duration(now));
+        let report = build_report(&store.tasks().unwrap(), DateRange::default(), Grouping::Day, now);
+        let days: Vec<(u32, u64)> = report.periods.iter().map(|(day, totals)| (day.day(), totals[&id])).collect();
+        assert_eq!(vec![(4, 10800), (5, 5400), (6, 3600)], days);
+        // Reading only the sessions of the 5th gives the same total for that day.
+        let fifth = DateRange { from: Some(local(5, 0, 0).date_naive()), to: Some(local(5, 0, 0).date_naive()) };
+        assert!(store.tasks_between(fifth).unwrap()[&id].sessions.is_empty());
+        assert_eq!(5400, build_report(&store.tasks_between(fifth).unwrap(), fifth, Grouping::Day, now).total());
+
+        clock.set(local(6, 2, 0));
+        let task = store.stop(id, clock.now()).unwrap();
+        assert_eq!(10800 + 12600, task.current_duration(local(9, 0, 0).into()));
+        let range = DateRange { from: Some(local(6, 0, 0).date_naive()), to: None };
+        let report = build_report(&store.tasks().unwrap(), range, Grouping::Day, clock.now());
+        assert_eq!(7200, report.total());
+    }
+
+    #[test]
+    fn fake_clock_decides_what_is_in_the_future() {
+        let clock = FakeClock::new(local(4, 9, 0));
+        let (_data_dir, store) = temp_store();
+        let store = store.with_clock(clock.clone());
+        let id = store.create("later", Vec::new(), None, None, false).unwrap().task.id;
+        assert!(matches!(store.start(id, local(4, 10, 0).into()), Err(TimerError::InvalidArgument(_))));
+        let session = Session { start: local(4, 8, 0).into(), end: local(4, 9, 30).into() };
+        assert!(store.log(id, session.clone()).is_err());
+
+        clock.set(local(4, 11, 0));
+        store.log(id, session).unwrap();
+        store.start(id, local(4, 10, 0).into()).unwrap();
+        assert_eq!(5400 + 3600, store.task(id).unwrap().current_duration(clock.now()));
+        let journal = Journal::load(store.storage().data_dir()).unwrap();
+        let times: Vec<SystemTime> = journal.entries.iter().map(|entry| entry.time).collect();
+        assert_eq!(vec![local(4, 9, 0).into(), clock.now(), clock.now()], times);
+    }
+
+    #[test]
+    fn suspended_sessions_are_reviewed_before_they_stop() {
+        let clock = FakeClock::new(local(4, 17, 0));
+        let (_data_dir, store) = temp_store();
+        let store = store.with_clock(clock.clone());
+        let id = store.create("late", Vec::new(), None, None, true).unwrap().task.id;
+        assert_eq!(Some(clock.now()), store.last_activity().unwrap());
+        clock.advance(Duration::from_secs(3600));
+        assert!(store.review_stop(id, clock.now()).unwrap().is_none());
+        store.tag(id, vec![String::from("evening")]).unwrap();
+
+        // The laptop was closed at 18:00 with the task running and opened the next morning.
+        clock.suspend(Duration::from_secs(15 * 3600));
+        // Looking at the tasks after the resume does not count as activity.
+        store.tasks().unwrap();
+        assert_eq!(Some(SystemTime::from(local(4, 18, 0))), store.last_activity().unwrap());
+        let review = store.review_stop(id, clock.now()).unwrap().unwrap();
+        assert_eq!(vec![Interruption::Suspended(15 * 3600)], review.interruptions);
+        assert_eq!(3600, review.session(Resolution::Cap).unwrap().duration());
+        let session = review.session(Resolution::LastActivity).unwrap().clone();
+        assert_eq!(SystemTime::from(local(4, 18, 0)), session.end);
+
+        let task = store.end_session(id, session).unwrap();
+        assert!(!task.running && task.start_mark.is_none());
+        assert_eq!(3600, task.current_duration(clock.now()));
+    }
+
+    #[test]
+    fn sessions_stopped_without_asking_are_capped() {
+        let clock = FakeClock::new(local(4, 17, 0));
+        let (_data_dir, store) = temp_store();
+        let store = store.with_clock(clock.clone()).with_exclusive_start(true);
+        let late = store.create("late", Vec::new(), None, None, true).unwrap().task.id;
+        let other = store.create("other", Vec::new(), None, None, false).unwrap().task.id;
+        clock.advance(Duration::from_secs(3600));
+        clock.suspend(Duration::from_secs(15 * 3600));
+
+        let started = store.switch(other, clock.now()).unwrap();
+        assert_eq!(vec![late], started.stopped);
+        assert_eq!(vec![Interruption::Suspended(15 * 3600)], started.interrupted[0].interruptions);
+        assert_eq!(3600, store.task(late).unwrap().current_duration(clock.now()));
+
+        clock.advance(Duration::from_secs(1800));
+        clock.suspend(Duration::from_secs(3600));
+        let (task, review) = store.stop_capped(other, clock.now()).unwrap();
+        assert!(review.is_some() && !task.running);
+        assert_eq!(1800, task.current_duration(clock.now()));
+    }
+
+    #[test]
+    fn pomodoros_are_recorded_and_reported() {
+        let clock = FakeClock::new(local(4, 9, 0));
+        let (_data_dir, store) = temp_store();
+        let store = store.with_clock(clock.clone());
+        let id = store.create("write", Vec::new(), None, None, false).unwrap().task.id;
+        for _ in 0..2 {
+            store.start(id, clock.now()).unwrap();
+            clock.advance(Duration::from_secs(25 * 60));
+            store.finish_pomodoro(id).unwrap();
+            clock.advance(Duration::from_secs(5 * 60));
+        }
+        let tasks = store.tasks().unwrap();
+        assert_eq!(2, tasks[&id].pomodoros.len());
+        let report = build_report(&tasks, DateRange::default(), Grouping::Day, clock.now());
+        assert_eq!((50 * 60, 2), (report.total(), report.pomodoro_total()));
+        store.step_history(true).unwrap();
+        assert_eq!(1, store.task(id).unwrap().pomodoros.len());
+    }
+
+    #[test]
+    fn idle_time_is_discarded_or_reassigned() {
+        let clock = FakeClock::new(local(4, 9, 0));
+        let (_data_dir, store) = temp_store();
+        let store = store.with_clock(clock.clone());
+        let report = store.create("report", Vec::new(), None, None, true).unwrap().task.id;
+        let meeting = store.create("meeting", Vec::new(), None, None, false).unwrap().task.id;
+        clock.advance(Duration::from_secs(3 * 3600));
+        // Away from 10:00 to 11:00 while the report ran.
+        let data_dir = store.storage().data_dir();
+        let mut log = IdleLog::default();
+        let period = log.record(Duration::from_secs(3600), local(4, 11, 0).into());
+        log.save(data_dir).unwrap();
+        let reviews = store.idle_reviews().unwrap();
+        assert_eq!(vec![(report, 3600)], reviews[0].tasks);
+
+        assert_eq!(3600, store.resolve_idle(&period, IdleDecision::Reassign(meeting)).unwrap());
+        let tasks = store.tasks().unwrap();
+        assert_eq!(2 * 3600, tasks[&report].current_duration(clock.now()));
+        assert!(tasks[&report].running);
+        let moved = &tasks[&meeting].sessions;
+        assert_eq!((1, period.start, period.end), (moved.len(), moved[0].start, moved[0].end));
+        assert!(store.idle_reviews().unwrap().is_empty());
+
+        // Recorded again, the meeting's hour goes away.
+        let mut log = IdleLog::default();
+        log.record(Duration::from_secs(3600), local(4, 11, 0).into());
+        log.save(data_dir).unwrap();
+        assert_eq!(vec![(meeting, 3600)], store.idle_reviews().unwrap()[0].tasks);
+        store.resolve_idle(&period, IdleDecision::Discard).unwrap();
+        assert_eq!(0, store.task(meeting).unwrap().current_duration(clock.now()));
+    }
+
+    #[test]
+    fn idle_periods_wait_for_every_list_that_ran() {
+        let clock = FakeClock::new(local(4, 9, 0));
+        let (temp_dir, store) = temp_store();
+        let current = store.with_clock(clock.clone());
+        let side = TaskStore::new(Box::new(JsonStorage::new(temp_dir.path(), 0))).with_clock(clock.clone());
+        let side = side.with_list("side").unwrap();
+        current.create("report", Vec::new(), None, None, true).unwrap();
+        let chores = side.create("chores", Vec::new(), None, None, true).unwrap().task.id;
+        clock.advance(Duration::from_secs(3 * 3600));
+        let data_dir = current.storage().data_dir();
+        let mut log = IdleLog::default();
+        let period = log.record(Duration::from_secs(3600), local(4, 11, 0).into());
+        log.save(data_dir).unwrap();
+
+        current.resolve_idle(&period, IdleDecision::Discard).unwrap();
+        assert!(current.idle_reviews().unwrap().is_empty());
+        assert_eq!(vec![(chores, 3600)], side.idle_reviews().unwrap()[0].tasks);
+        side.resolve_idle(&period, IdleDecision::Keep).unwrap();
+        assert!(IdleLog::load(data_dir).unwrap().periods.is_empty());
+    }
+}
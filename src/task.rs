@@ -1,49 +1,225 @@
+use std::collections::{BTreeMap, BTreeSet};
 use std::time::SystemTime;
 
 use chrono::{DateTime, Local};
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 
-use crate::utils::{format_duration, parse_time_string_to_seconds};
+use crate::clock::ClockMark;
+use crate::error::TimerError;
+use crate::duration::parse_duration;
+use crate::utils::format_duration;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Session {
+    pub start: SystemTime,
+    pub end: SystemTime,
+}
+
+impl Session {
+    pub fn duration(&self) -> u64 {
+        self.end.duration_since(self.start).unwrap_or_default().as_secs()
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "start": to_rfc3339(self.start),
+            "end": to_rfc3339(self.end),
+            "duration_seconds": self.duration(),
+        })
+    }
+}
+
+fn to_rfc3339(time: SystemTime) -> String {
+    DateTime::<Local>::from(time).to_rfc3339()
+}
+
+fn format_timestamp(time: SystemTime) -> String {
+    DateTime::<Local>::from(time).format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+// Rounded down, so 100% is only shown once the whole estimate is used.
+fn percent(duration: u64, estimate: u64) -> u64 {
+    (duration as u128 * 100 / estimate.max(1) as u128) as u64
+}
+
+fn check_not_in_future(time: SystemTime, now: SystemTime) -> Result<(), TimerError> {
+    if time > now {
+        return Err(TimerError::InvalidArgument(format!("{} is in the future", format_timestamp(time))));
+    }
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Task {
     pub id: u32,
     pub name: String,
-    pub total_duration_seconds: u64,
+    #[serde(default)]
+    pub sessions: Vec<Session>,
+    // Time not backed by a session: totals saved before sessions existed and
+    // manual add/sub/set adjustments. Can be negative after a subtraction.
+    #[serde(default, alias = "total_duration_seconds")]
+    pub adjustment_seconds: i64,
     pub running: bool,
     pub last_run: Option<SystemTime>,
+    #[serde(default)]
+    pub tags: BTreeSet<String>,
+    // Slash separated path, the first segment being the client. Example: acme/backend
+    #[serde(default)]
+    pub project: Option<String>,
+    // Clock readings from when the running session started, to detect suspends and
+    // clock changes when it stops.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_mark: Option<ClockMark>,
+    // End times of the pomodoros completed with `timer pomodoro`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pomodoros: Vec<SystemTime>,
+    // How long the task is expected to take.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub estimate_seconds: Option<u64>,
+}
+
+/// Criteria for the tasks shown by `list` and `report`.
+#[derive(Default, Debug)]
+pub struct TaskFilter {
+    pub tags: Vec<String>,
+    pub project: Option<String>,
+}
+
+impl TaskFilter {
+    pub fn matches(&self, task: &Task) -> bool {
+        task.has_tags(&self.tags)
+            && self.project.as_ref().is_none_or(|project| task.in_project(project))
+    }
+}
+
+/// Trims every segment of a `client/project` path and rejects empty segments.
+pub fn normalize_project(project: &str) -> Result<String, TimerError> {
+    let segments: Vec<&str> = project.split('/').map(str::trim).collect();
+    if segments.iter().any(|segment| segment.is_empty()) {
+        return Err(TimerError::InvalidArgument(format!("'{project}' is not a valid project")));
+    }
+    Ok(segments.join("/"))
+}
+
+/// Builds a project from segments written by hand or by other tools, dropping empty
+/// ones. `None` when no segment is left.
+pub fn project_from_segments<S: AsRef<str>>(segments: impl IntoIterator<Item = S>) -> Option<String> {
+    let segments: Vec<String> = segments
+        .into_iter()
+        .map(|segment| segment.as_ref().trim().to_string())
+        .filter(|segment| !segment.is_empty())
+        .collect();
+    normalize_project(&segments.join("/")).ok()
+}
+
+/// Sums durations for every level of the project hierarchy, so `acme/backend` also
+/// counts towards `acme`. Tasks without a project are under the empty path.
+pub fn project_totals<'a>(durations: impl IntoIterator<Item = (&'a Task, u64)>) -> BTreeMap<Vec<String>, u64> {
+    let mut totals = BTreeMap::new();
+    for (task, duration) in durations {
+        let segments: Vec<String> = task.project
+            .iter()
+            .flat_map(|project| project.split('/'))
+            .map(String::from)
+            .collect();
+        if segments.is_empty() {
+            *totals.entry(Vec::new()).or_insert(0) += duration;
+        }
+        for depth in 1..=segments.len() {
+            *totals.entry(segments[..depth].to_vec()).or_insert(0) += duration;
+        }
+    }
+    totals
+}
+
+/// Parses a duration given on the command line into seconds.
+pub fn parse_time(time: &str) -> Result<u64, TimerError> {
+    parse_duration(time).map_err(|err| TimerError::InvalidTime(String::from(time), err))
+}
+
+/// Strips the optional `+` prefix used on the command line and rejects empty tags.
+pub fn normalize_tag(tag: &str) -> Result<String, TimerError> {
+    let tag = tag.strip_prefix('+').unwrap_or(tag);
+    if tag.is_empty() || tag.chars().any(char::is_whitespace) {
+        return Err(TimerError::InvalidArgument(format!("'{tag}' is not a valid tag")));
+    }
+    Ok(String::from(tag))
+}
+
+/// Turns a tag written by hand or by other tools into a valid one by joining its words
+/// with `-`. `None` when nothing is left.
+pub fn tag_from_words(tag: &str) -> Option<String> {
+    let words: Vec<&str> = tag.split_whitespace().collect();
+    normalize_tag(&words.join("-")).ok()
+}
+
+/// Sums durations per tag, a task with several tags counts towards each of them.
+pub fn tag_totals<'a>(durations: impl IntoIterator<Item = (&'a Task, u64)>) -> BTreeMap<String, u64> {
+    let mut totals = BTreeMap::new();
+    for (task, duration) in durations {
+        for tag in &task.tags {
+            *totals.entry(tag.clone()).or_insert(0) += duration;
+        }
+    }
+    totals
 }
 
 impl Task {
-    pub fn to_print_string(&self, show_timestamp: bool, show_base_timer: bool) -> String {
-        let duration = self.current_duration();
+    pub fn new(id: u32, name: &str) -> Task {
+        Task {
+            id,
+            name: String::from(name),
+            sessions: Vec::new(),
+            adjustment_seconds: 0,
+            running: false,
+            last_run: None,
+            tags: BTreeSet::new(),
+            project: None,
+            start_mark: None,
+            pomodoros: Vec::new(),
+            estimate_seconds: None,
+        }
+    }
+
+    pub fn to_print_string(&self, now: SystemTime, show_timestamp: bool, show_base_timer: bool) -> String {
+        let duration = self.current_duration(now);
         let formatted_duration = format_duration(duration);
         let prefix = if self.running { "#" } else { "" };
+        let budget = match self.estimate_seconds {
+            Some(estimate) => {
+                let warning = if duration > estimate { " ! over budget" } else { "" };
+                format!(" / {} ({}%){}", format_duration(estimate), percent(duration, estimate), warning)
+            }
+            None => String::new(),
+        };
         let timestamp = self.get_timestamp(show_timestamp);
         let base_timer = self.get_base_timer_formatted(show_base_timer);
+        let project = self.project.as_ref().map_or(String::new(), |project| format!(" @{project}"));
+        let tags: String = self.tags.iter().map(|tag| format!(" +{tag}")).collect();
+        let pomodoros = match self.pomodoros.len() {
+            0 => String::new(),
+            count => format!(" - Pomodoros: {count}"),
+        };
         format!(
-            "{}[{}] '{}': {}{}{}",
-            prefix, self.id, self.name, formatted_duration, base_timer, timestamp
+            "{}[{}] '{}': {}{}{}{}{}{}{}",
+            prefix, self.id, self.name, formatted_duration, budget, project, tags, pomodoros, base_timer, timestamp
         )
     }
 
     fn get_timestamp(&self, show_timestamp: bool) -> String {
-        let timestamp: String = if show_timestamp && self.last_run.is_some() {
-            let last_run = self.last_run.unwrap();
-            let datetime: DateTime<Local> = last_run.into();
-            format!(
-                " - Last time: {}",
-                datetime.format("%d/%m/%Y %T").to_string()
-            )
-        } else {
-            String::new()
-        };
-        timestamp
+        match self.last_run {
+            Some(last_run) if show_timestamp => {
+                let datetime: DateTime<Local> = last_run.into();
+                format!(" - Last time: {}", datetime.format("%d/%m/%Y %T"))
+            }
+            _ => String::new(),
+        }
     }
 
     fn get_base_timer_formatted(&self, show_base_timer: bool) -> String {
         let timestamp: String = if show_base_timer {
-            let duration = self.total_duration_seconds;
+            let duration = self.base_duration();
             let formatted_duration = format_duration(duration);
             format!(
                 " - Base timer: {}",
@@ -55,124 +231,450 @@ impl Task {
         timestamp
     }
 
-    pub fn current_duration(&self) -> u64 {
-        let mut duration = self.total_duration_seconds;
-        if self.running {
-            let last_run = self.last_run.unwrap();
-            let current_running_duration = last_run.elapsed().unwrap_or_default().as_secs();
-            duration += current_running_duration;
+    fn sessions_duration(&self) -> u64 {
+        self.sessions.iter().map(Session::duration).sum()
+    }
+
+    /// Duration of the finished sessions plus adjustments, without the running session.
+    pub fn base_duration(&self) -> u64 {
+        let total = self.sessions_duration() as i64 + self.adjustment_seconds;
+        total.max(0) as u64
+    }
+
+    /// The session in progress, ending at `now`, if the task is running. A running task
+    /// without a start time is treated as stopped, `timer doctor` reports it.
+    pub fn running_session(&self, now: SystemTime) -> Option<Session> {
+        if !self.running {
+            return None;
+        }
+        let start = self.last_run?;
+        Some(Session { start, end: now.max(start) })
+    }
+
+    /// Finished sessions followed by the running one, if any.
+    pub fn sessions_until(&self, now: SystemTime) -> Vec<Session> {
+        let mut sessions = self.sessions.clone();
+        sessions.extend(self.running_session(now));
+        sessions
+    }
+
+    /// Total duration including the running session up to `now`.
+    pub fn current_duration(&self, now: SystemTime) -> u64 {
+        let mut duration = self.base_duration();
+        if let Some(session) = self.running_session(now) {
+            duration += session.duration();
         }
         duration
     }
 
-    pub fn start(&mut self) -> Result<(), ()> {
+    pub fn start(&mut self, now: SystemTime) -> Result<(), TimerError> {
+        self.start_at(now, now)
+    }
+
+    /// Starts the task as of `time`, which may be before `now` but not before the
+    /// end of its last session.
+    pub fn start_at(&mut self, time: SystemTime, now: SystemTime) -> Result<(), TimerError> {
         if self.running {
-            return Err(());
+            return Err(TimerError::AlreadyRunning(self.id));
+        }
+        check_not_in_future(time, now)?;
+        if let Some(last) = self.sessions.iter().map(|session| session.end).max() {
+            if time < last {
+                return Err(TimerError::InvalidArgument(format!(
+                    "Task {} has a session ending at {}, it cannot start before that",
+                    self.id,
+                    format_timestamp(last)
+                )));
+            }
         }
         self.running = true;
-        self.last_run = Some(SystemTime::now());
+        self.last_run = Some(time);
+        Ok(())
+    }
+
+    pub fn stop(&mut self, now: SystemTime) -> Result<(), TimerError> {
+        self.stop_at(now, now)
+    }
+
+    /// Stops the task as of `time`, which may be before `now` but not before it started.
+    pub fn stop_at(&mut self, time: SystemTime, now: SystemTime) -> Result<(), TimerError> {
+        let Some(session) = self.running_session(time) else {
+            return Err(TimerError::NotRunning(self.id));
+        };
+        check_not_in_future(time, now)?;
+        if time < session.start {
+            return Err(TimerError::InvalidArgument(format!(
+                "Task {} was started at {}, it cannot stop before that",
+                self.id,
+                format_timestamp(session.start)
+            )));
+        }
+        self.sessions.push(session);
+        self.running = false;
+        self.start_mark = None;
+        Ok(())
+    }
+
+    /// Stops the task at the end of a pomodoro's work interval and counts the pomodoro.
+    pub fn finish_pomodoro(&mut self, now: SystemTime) -> Result<(), TimerError> {
+        self.stop(now)?;
+        self.pomodoros.push(now);
         Ok(())
     }
 
-    pub fn stop(&mut self) -> Result<(), ()> {
+    /// Stops the task with `session` in place of the span since it started, for a session
+    /// corrected after a suspend or a clock change.
+    pub fn end_session(&mut self, session: Session, now: SystemTime) -> Result<(), TimerError> {
         if !self.running {
-            return Err(());
+            return Err(TimerError::NotRunning(self.id));
+        }
+        if session.end < session.start {
+            return Err(TimerError::InvalidArgument(String::from("The end of a session must be after its start")));
         }
-        let current_duration = self.total_duration_seconds;
-        let last_run = self.last_run.unwrap();
-        let duration_since_last_run = last_run.elapsed().unwrap_or_default().as_secs();
-        self.total_duration_seconds = current_duration + duration_since_last_run;
+        check_not_in_future(session.end, now)?;
+        if let Some(last) = self.sessions.iter().map(|other| other.end).max() {
+            if session.start < last {
+                return Err(TimerError::InvalidArgument(format!(
+                    "Task {} has a session ending at {}, the session cannot start before that",
+                    self.id,
+                    format_timestamp(last)
+                )));
+            }
+        }
+        self.last_run = Some(session.start);
+        self.sessions.push(session);
         self.running = false;
+        self.start_mark = None;
         Ok(())
     }
 
+    /// Removes the time within `period` from the sessions, splitting those it falls in, and
+    /// returns the parts removed. A running session is split too and runs on from the end
+    /// of `period`.
+    pub fn discard(&mut self, period: &Session, now: SystemTime) -> Vec<Session> {
+        let mut removed = Vec::new();
+        let mut kept = Vec::new();
+        for session in self.sessions.drain(..) {
+            if session.end <= period.start || period.end <= session.start {
+                kept.push(session);
+                continue;
+            }
+            if session.start < period.start {
+                kept.push(Session { start: session.start, end: period.start });
+            }
+            removed.push(Session { start: session.start.max(period.start), end: session.end.min(period.end) });
+            if period.end < session.end {
+                kept.push(Session { start: period.end, end: session.end });
+            }
+        }
+        if let Some(running) = self.running_session(now) {
+            if running.start < period.end && period.start < running.end {
+                if running.start < period.start {
+                    kept.push(Session { start: running.start, end: period.start });
+                }
+                removed.push(Session { start: running.start.max(period.start), end: running.end.min(period.end) });
+                // The clock readings belong to the old start.
+                self.last_run = Some(period.end.min(running.end));
+                self.start_mark = None;
+            }
+        }
+        self.sessions = kept;
+        removed
+    }
+
+    /// Records a finished session, for time that was not tracked live.
+    pub fn log(&mut self, session: Session, now: SystemTime) -> Result<(), TimerError> {
+        if session.end <= session.start {
+            return Err(TimerError::InvalidArgument(String::from("The end of a session must be after its start")));
+        }
+        check_not_in_future(session.end, now)?;
+        let overlapping = self
+            .sessions_until(now)
+            .into_iter()
+            .find(|other| other.start < session.end && session.start < other.end);
+        if let Some(other) = overlapping {
+            return Err(TimerError::InvalidArgument(format!(
+                "Task {} already has a session from {} to {}",
+                self.id,
+                format_timestamp(other.start),
+                format_timestamp(other.end)
+            )));
+        }
+        let index = self.sessions.partition_point(|other| other.start < session.start);
+        self.sessions.insert(index, session);
+        Ok(())
+    }
+
+    /// True if the task carries every one of `tags`.
+    pub fn has_tags(&self, tags: &[String]) -> bool {
+        tags.iter().all(|tag| self.tags.contains(tag))
+    }
+
+    /// True if the task belongs to `project` or to one of its sub-projects.
+    pub fn in_project(&self, project: &str) -> bool {
+        self.project.as_ref().is_some_and(|own| {
+            own == project || own.strip_prefix(project).is_some_and(|rest| rest.starts_with('/'))
+        })
+    }
+
     pub fn rename(&mut self, task_name: &str) {
         self.name = String::from(task_name);
     }
 
-    pub fn set_time(&mut self, time: &str) -> Result<(), ()> {
+    pub fn set_time(&mut self, time: &str) -> Result<(), TimerError> {
         if self.running {
-            return Err(());
+            return Err(TimerError::TaskRunning(self.id, "setting a new time"));
         }
-        let new_duration = parse_time_string_to_seconds(time).unwrap();
-        self.total_duration_seconds = new_duration;
+        let new_duration = parse_time(time)?;
+        self.adjustment_seconds = new_duration as i64 - self.sessions_duration() as i64;
         Ok(())
     }
 
-    pub fn add_time(&mut self, time: &str) {
-        let additional_time = parse_time_string_to_seconds(time).unwrap();
-        self.total_duration_seconds = self.total_duration_seconds + additional_time;
+    pub fn add_time(&mut self, time: &str) -> Result<(), TimerError> {
+        let additional_time = parse_time(time)?;
+        self.adjustment_seconds += additional_time as i64;
+        Ok(())
     }
 
-    pub fn subtract_time(&mut self, time: &str) -> Result<(), ()> {
-        let subtract_time = parse_time_string_to_seconds(time).unwrap();
-        if self.total_duration_seconds < subtract_time {
-            return Err(());
+    pub fn subtract_time(&mut self, time: &str) -> Result<(), TimerError> {
+        let subtract_time = parse_time(time)?;
+        if self.base_duration() < subtract_time {
+            return Err(TimerError::NotEnoughTime(self.id));
         }
-        self.total_duration_seconds = self.total_duration_seconds - subtract_time;
+        self.adjustment_seconds -= subtract_time as i64;
         Ok(())
     }
 
     pub fn formatted_duration(&self) -> String {
-        format_duration(self.total_duration_seconds)
+        format_duration(self.base_duration())
+    }
+
+    /// Task fields plus the computed durations, for the JSON output mode.
+    pub fn to_json(&self, now: SystemTime) -> Value {
+        let duration = self.current_duration(now);
+        let estimate = self.estimate_seconds;
+        json!({
+            "id": self.id,
+            "name": self.name,
+            "running": self.running,
+            "duration_seconds": duration,
+            "duration": format_duration(duration),
+            "base_duration_seconds": self.base_duration(),
+            "last_run": self.last_run.map(to_rfc3339),
+            "tags": self.tags,
+            "project": self.project,
+            "sessions": self.sessions.iter().map(Session::to_json).collect::<Vec<Value>>(),
+            "pomodoros": self.pomodoros.len(),
+            "estimate_seconds": estimate,
+            "budget_used_percent": estimate.map(|estimate| percent(duration, estimate)),
+            "over_budget": estimate.is_some_and(|estimate| duration > estimate),
+        })
     }
 }
 
 #[cfg(test)]
 mod tests {
     use std::ops::Sub;
+    use std::time::Duration;
 
     use super::*;
 
+    // A fixed point in time, so durations of running tasks do not depend on the wall clock.
+    fn now() -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::new(1_700_000_000, 0)
+    }
+
+    fn task_with_seconds(seconds: i64) -> Task {
+        let mut task = Task::new(1, "my task");
+        task.adjustment_seconds = seconds;
+        task
+    }
+
     #[test]
     fn to_print_string_default() {
-        let t = Task {
-            id: 1,
-            name: String::from("my task"),
-            total_duration_seconds: 60,
-            running: false,
-            last_run: None,
-        };
-        let print_string = t.to_print_string(false, false);
+        let t = task_with_seconds(60);
+        let print_string = t.to_print_string(now(), false, false);
         assert_eq!("[1] 'my task': 00:01:00", print_string);
     }
 
     #[test]
     fn to_print_string_running() {
-        let t = Task {
-            id: 1,
-            name: String::from("my task"),
-            total_duration_seconds: 60,
-            running: true,
-            last_run: Some(SystemTime::now()),
-        };
-        let print_string = t.to_print_string(false, false);
+        let mut t = task_with_seconds(60);
+        t.running = true;
+        t.last_run = Some(now());
+        let print_string = t.to_print_string(now(), false, false);
         assert_eq!("#[1] 'my task': 00:01:00", print_string);
     }
 
     #[test]
     fn to_print_string_running_sub() {
-        let task = Task {
-            id: 1,
-            name: String::from("my task"),
-            total_duration_seconds: 60,
-            running: true,
-            last_run: Some(SystemTime::now().sub(std::time::Duration::new(5, 0))),
-        };
-        let print_string = task.to_print_string(false, false);
+        let mut task = task_with_seconds(60);
+        task.running = true;
+        task.last_run = Some(now().sub(Duration::new(5, 0)));
+        let print_string = task.to_print_string(now(), false, false);
         assert_eq!("#[1] 'my task': 00:01:05", print_string);
     }
 
     #[test]
     fn to_print_string_running_sub_show_base() {
-        let task = Task {
-            id: 1,
-            name: String::from("my task"),
-            total_duration_seconds: 60,
-            running: true,
-            last_run: Some(SystemTime::now().sub(std::time::Duration::new(5, 0))),
-        };
-        let print_string = task.to_print_string(false, true);
+        let mut task = task_with_seconds(60);
+        task.running = true;
+        task.last_run = Some(now().sub(Duration::new(5, 0)));
+        let print_string = task.to_print_string(now(), false, true);
         assert_eq!("#[1] 'my task': 00:01:05 - Base timer: 00:01:00", print_string);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn to_print_string_tags() {
+        let mut t = task_with_seconds(60);
+        t.tags.insert(String::from("bug"));
+        t.tags.insert(String::from("backend"));
+        let print_string = t.to_print_string(now(), false, false);
+        assert_eq!("[1] 'my task': 00:01:00 +backend +bug", print_string);
+    }
+
+    #[test]
+    fn to_print_string_estimate() {
+        let mut t = task_with_seconds(3 * 3600);
+        t.estimate_seconds = Some(4 * 3600);
+        assert_eq!("[1] 'my task': 03:00:00 / 04:00:00 (75%)", t.to_print_string(now(), false, false));
+        t.add_time("1h30m").unwrap();
+        assert_eq!("[1] 'my task': 04:30:00 / 04:00:00 (112%) ! over budget", t.to_print_string(now(), false, false));
+        let json = t.to_json(now());
+        assert_eq!((json!(112), json!(true)), (json["budget_used_percent"].clone(), json["over_budget"].clone()));
+    }
+
+    #[test]
+    fn tags_are_normalized() {
+        assert_eq!("urgent", normalize_tag("+urgent").unwrap());
+        assert_eq!("urgent", normalize_tag("urgent").unwrap());
+        assert!(normalize_tag("+").is_err());
+        assert!(normalize_tag("two words").is_err());
+        assert_eq!(Some(String::from("two-words")), tag_from_words(" two  words "));
+        assert_eq!(None, tag_from_words("  "));
+    }
+
+    #[test]
+    fn project_hierarchy() {
+        let mut backend = task_with_seconds(60);
+        backend.project = Some(normalize_project("acme / backend").unwrap());
+        let mut frontend = task_with_seconds(30);
+        frontend.project = Some(String::from("acme/frontend"));
+        let other = task_with_seconds(10);
+        assert!(backend.in_project("acme"));
+        assert!(!backend.in_project("acm"));
+        assert!(normalize_project("acme//backend").is_err());
+        assert_eq!(Some(String::from("acme/backend")), project_from_segments("acme// backend ".split('/')));
+        assert_eq!(None, project_from_segments(["", " "]));
+
+        let totals = project_totals([(&backend, 60), (&frontend, 30), (&other, 10)]);
+        let path = |p: &[&str]| p.iter().map(|s| s.to_string()).collect::<Vec<String>>();
+        assert_eq!(90, totals[&path(&["acme"])]);
+        assert_eq!(60, totals[&path(&["acme", "backend"])]);
+        assert_eq!(10, totals[&path(&[])]);
+        assert_eq!("[1] 'my task': 00:01:00 @acme/backend", backend.to_print_string(now(), false, false));
+    }
+
+    #[test]
+    fn stop_records_session() {
+        let mut task = Task::new(1, "my task");
+        let started = now().sub(Duration::new(90, 0));
+        task.running = true;
+        task.last_run = Some(started);
+        task.stop(now()).unwrap();
+        assert!(!task.running);
+        assert_eq!(1, task.sessions.len());
+        assert_eq!(started, task.sessions[0].start);
+        assert_eq!(90, task.current_duration(now()));
+    }
+
+    #[test]
+    fn finished_pomodoros_are_counted() {
+        let mut task = task_with_seconds(0);
+        task.start(now().sub(Duration::new(25 * 60, 0))).unwrap();
+        task.finish_pomodoro(now()).unwrap();
+        assert!(!task.running);
+        assert_eq!(vec![now()], task.pomodoros);
+        assert_eq!("[1] 'my task': 00:25:00 - Pomodoros: 1", task.to_print_string(now(), false, false));
+        assert!(task.finish_pomodoro(now()).is_err());
+        assert_eq!(1, task.pomodoros.len());
+    }
+
+    #[test]
+    fn start_and_stop_in_the_past() {
+        let mut task = Task::new(1, "my task");
+        let now = now();
+        assert!(task.start_at(now + Duration::new(60, 0), now).is_err());
+        task.start_at(now.sub(Duration::new(600, 0)), now).unwrap();
+        assert!(task.stop_at(now.sub(Duration::new(900, 0)), now).is_err());
+        task.stop_at(now.sub(Duration::new(300, 0)), now).unwrap();
+        assert_eq!(300, task.current_duration(now));
+        assert!(task.start_at(now.sub(Duration::new(400, 0)), now).is_err());
+    }
+
+    #[test]
+    fn log_rejects_overlapping_sessions() {
+        let mut task = Task::new(1, "my task");
+        let now = now();
+        let at = |seconds_ago: u64| now.sub(Duration::new(seconds_ago, 0));
+        task.log(Session { start: at(600), end: at(300) }, now).unwrap();
+        task.log(Session { start: at(1200), end: at(900) }, now).unwrap();
+        assert_eq!(at(1200), task.sessions[0].start);
+        assert!(task.log(Session { start: at(700), end: at(500) }, now).is_err());
+        assert!(task.log(Session { start: at(100), end: at(200) }, now).is_err());
+        assert!(task.log(Session { start: at(100), end: now + Duration::new(60, 0) }, now).is_err());
+        assert_eq!(600, task.current_duration(now));
+    }
+
+    #[test]
+    fn adjustments_apply_on_top_of_sessions() {
+        let mut task = Task::new(1, "my task");
+        let end = now();
+        task.sessions.push(Session { start: end.sub(Duration::new(3600, 0)), end });
+        task.add_time("30m").unwrap();
+        assert_eq!(5400, task.current_duration(now()));
+        task.subtract_time("1h15m").unwrap();
+        assert_eq!(900, task.current_duration(now()));
+        assert!(task.subtract_time("1h").is_err());
+        task.set_time("2h").unwrap();
+        assert_eq!(7200, task.current_duration(now()));
+        assert_eq!(1, task.sessions.len());
+    }
+
+    #[test]
+    fn invalid_time_is_an_error() {
+        let mut task = Task::new(1, "my task");
+        assert!(matches!(task.add_time("m1h"), Err(TimerError::InvalidTime(_, _))));
+        assert!(matches!(task.set_time("abc"), Err(TimerError::InvalidTime(_, _))));
+        assert_eq!(0, task.current_duration(now()));
+    }
+
+    #[test]
+    fn load_legacy_total_duration() {
+        let json = r#"{"id":1,"name":"my task","total_duration_seconds":120,"running":false,"last_run":null}"#;
+        let task: Task = serde_json::from_str(json).unwrap();
+        assert!(task.sessions.is_empty());
+        assert_eq!(120, task.current_duration(now()));
+    }
+
+    #[test]
+    fn discard_splits_sessions() {
+        let now = now();
+        let at = |minutes_ago: u64| now.sub(Duration::new(minutes_ago * 60, 0));
+        let mut task = Task::new(1, "my task");
+        task.sessions.push(Session { start: at(180), end: at(120) });
+        task.running = true;
+        task.last_run = Some(at(60));
+        let removed = task.discard(&Session { start: at(150), end: at(130) }, now);
+        assert_eq!(1200, removed[0].duration());
+        assert_eq!(2, task.sessions.len());
+        assert_eq!(at(130), task.sessions[1].start);
+
+        // Away for the middle 40 minutes of the running session.
+        let removed = task.discard(&Session { start: at(50), end: at(10) }, now);
+        assert_eq!(2400, removed[0].duration());
+        assert!(task.running);
+        assert_eq!(Some(at(10)), task.last_run);
+        assert_eq!(3600 - 1200 + 600 + 600, task.current_duration(now));
+    }
+}
@@ -0,0 +1,125 @@
+use chrono::{DateTime, Days, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone};
+
+use crate::duration::parse_duration;
+
+const TIME_FORMATS: [&str; 2] = ["%H:%M", "%H:%M:%S"];
+const DATE_TIME_FORMATS: [&str; 4] = ["%Y-%m-%d %H:%M", "%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%dT%H:%M:%S"];
+
+fn parse_time_of_day(input: &str) -> Option<NaiveTime> {
+    TIME_FORMATS
+        .iter()
+        .find_map(|format| NaiveTime::parse_from_str(input, format).ok())
+}
+
+fn at_local(date: NaiveDate, time: NaiveTime) -> Option<DateTime<Local>> {
+    Local.from_local_datetime(&date.and_time(time)).earliest()
+}
+
+/// Parses a point in time relative to `now`. Accepted formats:
+/// - `now`
+/// - a time of day, today: `09:15`, `17:30:10`
+/// - `today 09:15` and `yesterday 17:30`
+/// - a duration followed by `ago`: `20 minutes ago`, `1h30m ago`
+/// - a date and time: `2024-03-04 09:15`, `2024-03-04T09:15:00` or RFC 3339
+pub fn parse_timestamp(input: &str, now: DateTime<Local>) -> Option<DateTime<Local>> {
+    let input = input.trim();
+    let lowercase = input.to_lowercase();
+    if lowercase == "now" {
+        return Some(now);
+    }
+    if let Some(duration) = lowercase.strip_suffix("ago") {
+        let seconds = parse_duration(duration).ok()?;
+        return now.checked_sub_signed(chrono::Duration::try_seconds(seconds as i64)?);
+    }
+    if let Some(time) = parse_time_of_day(input) {
+        return at_local(now.date_naive(), time);
+    }
+    if let Some((day, time)) = lowercase.split_once(char::is_whitespace) {
+        let date = match day {
+            "today" => Some(now.date_naive()),
+            "yesterday" => now.date_naive().checked_sub_days(Days::new(1)),
+            _ => None,
+        };
+        if let (Some(date), Some(time)) = (date, parse_time_of_day(time.trim())) {
+            return at_local(date, time);
+        }
+    }
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(input) {
+        return Some(datetime.with_timezone(&Local));
+    }
+    DATE_TIME_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(input, format).ok())
+        .and_then(|datetime| Local.from_local_datetime(&datetime).earliest())
+}
+
+/// Parses `START-END`, for example `09:00-10:30` or `yesterday 09:00-10:30`. An end
+/// given as a bare time of day is on the same day as the start, or the next day when
+/// that would be before the start.
+pub fn parse_interval(input: &str, now: DateTime<Local>) -> Option<(DateTime<Local>, DateTime<Local>)> {
+    // Dates contain dashes as well, so try every dash until both sides parse.
+    input.match_indices('-').find_map(|(index, _)| {
+        let (start, end) = (&input[..index], &input[index + 1..]);
+        let start = parse_timestamp(start, now)?;
+        let end = match parse_time_of_day(end.trim()) {
+            Some(time) => {
+                let same_day = at_local(start.date_naive(), time)?;
+                if same_day < start {
+                    at_local(start.date_naive().succ_opt()?, time)?
+                } else {
+                    same_day
+                }
+            }
+            None => parse_timestamp(end, now)?,
+        };
+        Some((start, end))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local(y: i32, m: u32, d: u32, h: u32, min: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(y, m, d, h, min, 0).unwrap()
+    }
+
+    #[test]
+    fn relative_timestamps() {
+        let now = local(2024, 3, 5, 12, 0);
+        assert_eq!(Some(now), parse_timestamp("now", now));
+        assert_eq!(Some(local(2024, 3, 5, 9, 15)), parse_timestamp("09:15", now));
+        assert_eq!(Some(local(2024, 3, 5, 9, 15)), parse_timestamp("today 09:15", now));
+        assert_eq!(Some(local(2024, 3, 4, 17, 30)), parse_timestamp("yesterday 17:30", now));
+        assert_eq!(Some(local(2024, 3, 5, 11, 40)), parse_timestamp("20 minutes ago", now));
+        assert_eq!(Some(local(2024, 3, 5, 10, 30)), parse_timestamp("1h30m ago", now));
+    }
+
+    #[test]
+    fn absolute_timestamps() {
+        let now = local(2024, 3, 5, 12, 0);
+        assert_eq!(Some(local(2024, 3, 1, 8, 5)), parse_timestamp("2024-03-01 08:05", now));
+        assert_eq!(Some(local(2024, 3, 1, 8, 5)), parse_timestamp("2024-03-01T08:05:00", now));
+        assert_eq!(None, parse_timestamp("tomorrow 10:00", now));
+        assert_eq!(None, parse_timestamp("25:00", now));
+        assert_eq!(None, parse_timestamp("ago", now));
+    }
+
+    #[test]
+    fn intervals() {
+        let now = local(2024, 3, 5, 12, 0);
+        assert_eq!(
+            Some((local(2024, 3, 5, 9, 0), local(2024, 3, 5, 10, 30))),
+            parse_interval("09:00-10:30", now)
+        );
+        assert_eq!(
+            Some((local(2024, 3, 4, 9, 0), local(2024, 3, 4, 10, 30))),
+            parse_interval("yesterday 09:00-10:30", now)
+        );
+        assert_eq!(
+            Some((local(2024, 3, 3, 23, 0), local(2024, 3, 4, 1, 0))),
+            parse_interval("2024-03-03 23:00 - 01:00", now)
+        );
+        assert_eq!(None, parse_interval("09:00", now));
+    }
+}
@@ -0,0 +1,370 @@
+use std::collections::HashMap;
+use std::io::{self, Stdout, Write};
+use std::time::Duration;
+
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, Print, SetAttribute};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+
+use simple_task_timer::error::TimerError;
+use simple_task_timer::store::TaskStore;
+use simple_task_timer::task::Task;
+use simple_task_timer::utils::format_duration;
+
+const HELP: &str = "up/down select  s start/stop  n new  r rename  a add  - subtract  A archive  q quit";
+// How often the screen is redrawn and the task file reloaded when no key is pressed.
+const REFRESH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Text being typed at the bottom of the screen, and what to do with it on Enter.
+enum Prompt {
+    Create,
+    Rename(u32),
+    AddTime(u32),
+    SubtractTime(u32),
+}
+
+impl Prompt {
+    fn label(&self) -> &'static str {
+        match self {
+            Prompt::Create => "New task name: ",
+            Prompt::Rename(_) => "New name: ",
+            Prompt::AddTime(_) => "Time to add (e.g. 1h30m): ",
+            Prompt::SubtractTime(_) => "Time to subtract (e.g. 1h30m): ",
+        }
+    }
+}
+
+struct App<'a> {
+    store: &'a TaskStore,
+    tasks: HashMap<u32, Task>,
+    selected: usize,
+    prompt: Option<(Prompt, String)>,
+    status: String,
+}
+
+impl App<'_> {
+    fn new(store: &TaskStore) -> App<'_> {
+        App {
+            store,
+            tasks: HashMap::new(),
+            selected: 0,
+            prompt: None,
+            status: String::new(),
+        }
+    }
+
+    fn sorted_ids(&self) -> Vec<u32> {
+        let mut ids: Vec<u32> = self.tasks.keys().copied().collect();
+        ids.sort();
+        ids
+    }
+
+    fn selected_id(&self) -> Option<u32> {
+        self.sorted_ids().get(self.selected).copied()
+    }
+
+    // Reloaded on every refresh so changes made by other `timer` invocations show up.
+    fn reload(&mut self) -> Result<(), TimerError> {
+        self.tasks = self.store.tasks()?;
+        self.selected = self.selected.min(self.tasks.len().saturating_sub(1));
+        Ok(())
+    }
+
+    /// Runs `action`, which changes the tasks through the store, then shows the returned
+    /// message or the error.
+    fn update(&mut self, action: impl FnOnce(&TaskStore) -> Result<String, TimerError>) {
+        let result = action(self.store);
+        self.status = match result {
+            Ok(message) => message,
+            Err(err) => format!("Error: {err}"),
+        };
+        if let Err(err) = self.reload() {
+            self.status = format!("Error: {err}");
+        }
+    }
+
+    fn toggle_selected(&mut self) {
+        let Some(id) = self.selected_id() else {
+            self.status = String::from("There are no tasks.");
+            return;
+        };
+        let running = self.tasks.get(&id).is_some_and(|task| task.running);
+        self.update(|store| {
+            // There is no room to ask how to end an interrupted session, it is capped.
+            if running {
+                let (task, review) = store.stop_capped(id, store.clock().now())?;
+                return Ok(match review {
+                    Some(review) => format!(
+                        "Task {id} looked interrupted, recorded {} of {}",
+                        format_duration(review.capped.duration()),
+                        format_duration(review.full.duration())
+                    ),
+                    None => format!("Task {} stopped", task.id),
+                });
+            }
+            let started = store.start(id, store.clock().now())?;
+            let capped = |id: &u32| started.interrupted.iter().any(|review| review.task_id == *id);
+            let stopped: Vec<String> = started
+                .stopped
+                .iter()
+                .map(|id| if capped(id) { format!("Task {id} stopped and capped, ") } else { format!("Task {id} stopped, ") })
+                .collect();
+            Ok(format!("{}Task {id} started", stopped.concat()))
+        });
+    }
+
+    fn archive_selected(&mut self) {
+        let Some(id) = self.selected_id() else {
+            return;
+        };
+        self.update(|store| {
+            let arch_task = store.archive(id)?;
+            Ok(format!("Task {id} archived with archive id {}", arch_task.id))
+        });
+    }
+
+    fn submit(&mut self, prompt: Prompt, input: String) {
+        match prompt {
+            Prompt::Create => self.update(|store| {
+                let created = store.create(&input, Vec::new(), None, None, false)?;
+                Ok(format!("Task {input} created with id {}", created.task.id))
+            }),
+            Prompt::Rename(id) => self.update(|store| {
+                store.rename(id, &input)?;
+                Ok(format!("Task {id} renamed to {input}"))
+            }),
+            Prompt::AddTime(id) => self.update(|store| {
+                let task = store.add_time(id, &input)?;
+                Ok(format!("Added {input} to task with id {id}, new timer: {}", task.formatted_duration()))
+            }),
+            Prompt::SubtractTime(id) => self.update(|store| {
+                let task = store.subtract_time(id, &input)?;
+                Ok(format!("Subtracted {input} from task {id}, new timer: {}", task.formatted_duration()))
+            }),
+        }
+    }
+
+    fn open_prompt(&mut self, prompt: impl FnOnce(u32) -> Prompt) {
+        if let Some(id) = self.selected_id() {
+            self.prompt = Some((prompt(id), String::new()));
+        }
+    }
+
+    /// Returns false when the user asked to quit.
+    fn handle_key(&mut self, key: KeyEvent) -> bool {
+        if let Some((prompt, mut input)) = self.prompt.take() {
+            match key.code {
+                KeyCode::Enter if !input.trim().is_empty() => self.submit(prompt, input.trim().to_string()),
+                KeyCode::Esc | KeyCode::Enter => {}
+                KeyCode::Backspace => {
+                    input.pop();
+                    self.prompt = Some((prompt, input));
+                }
+                KeyCode::Char(c) => {
+                    input.push(c);
+                    self.prompt = Some((prompt, input));
+                }
+                _ => self.prompt = Some((prompt, input)),
+            }
+            return true;
+        }
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return false,
+            KeyCode::Up | KeyCode::Char('k') => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.selected = (self.selected + 1).min(self.tasks.len().saturating_sub(1))
+            }
+            KeyCode::Char('s') | KeyCode::Char(' ') | KeyCode::Enter => self.toggle_selected(),
+            KeyCode::Char('n') => self.prompt = Some((Prompt::Create, String::new())),
+            KeyCode::Char('r') => self.open_prompt(Prompt::Rename),
+            KeyCode::Char('a') | KeyCode::Char('+') => self.open_prompt(Prompt::AddTime),
+            KeyCode::Char('-') => self.open_prompt(Prompt::SubtractTime),
+            KeyCode::Char('A') => self.archive_selected(),
+            _ => {}
+        }
+        true
+    }
+
+    fn draw(&self, stdout: &mut Stdout) -> io::Result<()> {
+        let (width, height) = terminal::size()?;
+        queue!(stdout, Clear(ClearType::All), MoveTo(0, 0))?;
+        let now = self.store.clock().now();
+        let total: u64 = self.tasks.values().map(|task| task.current_duration(now)).sum();
+        queue!(
+            stdout,
+            SetAttribute(Attribute::Bold),
+            Print(fit(&format!("{} tasks - Total: {}", self.store.list(), format_duration(total)), width)),
+            SetAttribute(Attribute::Reset),
+        )?;
+
+        let ids = self.sorted_ids();
+        if ids.is_empty() {
+            queue!(stdout, MoveTo(0, 2), Print("There are no tasks. Press n to create one."))?;
+        }
+        // Keep the selected task on screen, 5 lines are used by the header and footer.
+        let visible = height.saturating_sub(5).max(1) as usize;
+        let first = self.selected.saturating_sub(visible - 1);
+        for (row, (index, id)) in ids.iter().enumerate().skip(first).take(visible).enumerate() {
+            let line = fit(&self.tasks[id].to_print_string(now, false, false), width);
+            queue!(stdout, MoveTo(0, row as u16 + 2))?;
+            if index == self.selected {
+                queue!(stdout, SetAttribute(Attribute::Reverse), Print(line), SetAttribute(Attribute::Reset))?;
+            } else {
+                queue!(stdout, Print(line))?;
+            }
+        }
+
+        queue!(stdout, MoveTo(0, height.saturating_sub(2)), Print(fit(&self.status, width)))?;
+        queue!(stdout, MoveTo(0, height.saturating_sub(1)))?;
+        match &self.prompt {
+            Some((prompt, input)) => queue!(stdout, Print(fit(&format!("{}{input}", prompt.label()), width)), Show)?,
+            None => queue!(stdout, SetAttribute(Attribute::Dim), Print(fit(HELP, width)), SetAttribute(Attribute::Reset), Hide)?,
+        }
+        stdout.flush()
+    }
+}
+
+// Lines longer than the terminal would wrap onto the ones below them.
+fn fit(line: &str, width: u16) -> String {
+    line.chars().take(usize::from(width)).collect()
+}
+
+// Puts the terminal back to normal even when the loop returns early with an error.
+struct TerminalGuard;
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+pub fn run(store: &TaskStore) -> Result<(), TimerError> {
+    let mut app = App::new(store);
+    app.reload()?;
+
+    let mut stdout = io::stdout();
+    terminal::enable_raw_mode().map_err(TimerError::io("terminal"))?;
+    let _guard = TerminalGuard;
+    execute!(stdout, EnterAlternateScreen, Hide).map_err(TimerError::io("terminal"))?;
+
+    loop {
+        app.draw(&mut stdout).map_err(TimerError::io("terminal"))?;
+        if !event::poll(REFRESH_INTERVAL).map_err(TimerError::io("terminal"))? {
+            if let Err(err) = app.reload() {
+                app.status = format!("Error: {err}");
+            }
+            continue;
+        }
+        if let Event::Key(key) = event::read().map_err(TimerError::io("terminal"))? {
+            if key.kind == KeyEventKind::Press && !app.handle_key(key) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::{Local, TimeZone};
+    use simple_task_timer::clock::FakeClock;
+    use simple_task_timer::config::Config;
+    use tempfile::TempDir;
+
+    fn temp_store() -> (TempDir, TaskStore) {
+        let data_dir = tempfile::tempdir().unwrap();
+        let now = Local.with_ymd_and_hms(2024, 3, 4, 9, 0, 0).unwrap();
+        let store = TaskStore::open(data_dir.path(), &Config::default()).unwrap().with_clock(FakeClock::new(now));
+        (data_dir, store)
+    }
+
+    fn press(app: &mut App, code: KeyCode) -> bool {
+        app.handle_key(KeyEvent::new(code, KeyModifiers::NONE))
+    }
+
+    fn type_text(app: &mut App, text: &str) {
+        for c in text.chars() {
+            press(app, KeyCode::Char(c));
+        }
+    }
+
+    #[test]
+    fn creates_starts_stops_and_archives_a_task() {
+        let (_data_dir, store) = temp_store();
+        let mut app = App::new(&store);
+        app.reload().unwrap();
+
+        press(&mut app, KeyCode::Char('n'));
+        type_text(&mut app, "write docs");
+        press(&mut app, KeyCode::Enter);
+        assert!(app.prompt.is_none());
+        assert_eq!("write docs", app.tasks[&1].name);
+
+        press(&mut app, KeyCode::Char('s'));
+        assert!(store.tasks().unwrap()[&1].running);
+        press(&mut app, KeyCode::Char('s'));
+        assert!(!store.tasks().unwrap()[&1].running);
+
+        press(&mut app, KeyCode::Char('A'));
+        assert!(app.tasks.is_empty());
+        assert_eq!(1, store.tasks_in("archive").unwrap().len());
+    }
+
+    #[test]
+    fn dropped_and_blank_prompts_change_nothing() {
+        let (_data_dir, store) = temp_store();
+        let mut app = App::new(&store);
+
+        press(&mut app, KeyCode::Char('n'));
+        type_text(&mut app, "never");
+        // Keys typed into a prompt are not commands, q does not quit.
+        assert!(press(&mut app, KeyCode::Char('q')));
+        press(&mut app, KeyCode::Esc);
+        assert!(app.prompt.is_none());
+
+        press(&mut app, KeyCode::Char('n'));
+        type_text(&mut app, "  ");
+        press(&mut app, KeyCode::Enter);
+        assert!(app.prompt.is_none());
+        assert!(store.tasks().unwrap().is_empty());
+    }
+
+    #[test]
+    fn selection_stays_within_the_list() {
+        let (_data_dir, store) = temp_store();
+        let mut app = App::new(&store);
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Up);
+        press(&mut app, KeyCode::Up);
+        assert_eq!(0, app.selected);
+        assert_eq!(None, app.selected_id());
+
+        for name in ["one", "two", "three"] {
+            store.create(name, Vec::new(), None, None, false).unwrap();
+        }
+        app.reload().unwrap();
+        for _ in 0..5 {
+            press(&mut app, KeyCode::Down);
+        }
+        assert_eq!(Some(3), app.selected_id());
+
+        // Another `timer` invocation deletes tasks under the open interface.
+        store.delete(3).unwrap();
+        store.delete(2).unwrap();
+        app.reload().unwrap();
+        assert_eq!(Some(1), app.selected_id());
+        press(&mut app, KeyCode::Down);
+        assert_eq!(Some(1), app.selected_id());
+    }
+
+    #[test]
+    fn long_lines_are_cut_to_the_width() {
+        assert_eq!("wri", fit("write docs", 3));
+        assert_eq!("write docs", fit("write docs", 80));
+    }
+}